@@ -0,0 +1,112 @@
+use nannou::{geom::Rect, glam::Vec2, Draw};
+
+use crate::snake::Direction;
+
+#[derive(PartialEq, Clone, Copy)]
+pub struct Layout {
+    pub center: Vec2,
+    pub focus: Vec2,
+    pub cell: f32,
+}
+
+impl Layout {
+    pub fn new(rect: Rect, borders: (i32, i32, i32, i32), camera: &Camera) -> Layout {
+        let width = (borders.2 - borders.0) as f32;
+        let height = (borders.3 - borders.1) as f32;
+        Layout {
+            center: rect.xy(),
+            focus: camera.position,
+            cell: (rect.w() / width).min(rect.h() / height) * camera.zoom,
+        }
+    }
+
+    pub fn to_screen(self, point: Vec2) -> Vec2 {
+        self.center + (point - self.focus) * self.cell
+    }
+
+    pub fn to_board(self, point: Vec2) -> Vec2 {
+        (point - self.center) / self.cell + self.focus
+    }
+
+    pub fn cell_center(self, (x, y): (i32, i32)) -> Vec2 {
+        self.to_screen(Vec2::new(x as f32 + 0.5, y as f32 + 0.5))
+    }
+
+    pub fn cell_at(self, point: Vec2) -> (i32, i32) {
+        let cell = self.to_board(point).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+
+    pub fn transform(self, draw: &Draw) -> Draw {
+        draw.translate((self.center - self.focus * self.cell).extend(0.0)).scale(self.cell)
+    }
+}
+
+pub struct Camera {
+    pub zoom: f32,
+    pub pan: Vec2,
+    pub follow: bool,
+    pub position: Vec2,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+            follow: false,
+            position: Vec2::ZERO,
+        }
+    }
+}
+
+impl Camera {
+    const MAX_ZOOM: f32 = 16.0;
+    pub const ZOOM_STEP: f32 = 1.25;
+    const PAN_STEP: f32 = 0.1;
+    const SMOOTHING: f32 = 8.0;
+
+    fn board_center(borders: (i32, i32, i32, i32)) -> Vec2 {
+        Vec2::new((borders.0 + borders.2) as f32, (borders.1 + borders.3) as f32) / 2.0
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(1.0, Camera::MAX_ZOOM);
+    }
+
+    pub fn pan_by(&mut self, offset: Vec2, borders: (i32, i32, i32, i32)) {
+        if self.follow {
+            self.follow = false;
+            self.pan = self.position - Camera::board_center(borders);
+        }
+        let half = Vec2::new((borders.2 - borders.0) as f32, (borders.3 - borders.1) as f32) / 2.0;
+        let before = self.pan;
+        self.pan = (self.pan + offset).clamp(-half, half);
+        self.position += self.pan - before;
+    }
+
+    pub fn nudge(&mut self, direction: Direction, borders: (i32, i32, i32, i32)) {
+        let offset = match direction {
+            Direction::Up => Vec2::Y,
+            Direction::Down => -Vec2::Y,
+            Direction::Left => -Vec2::X,
+            Direction::Right => Vec2::X,
+        };
+        let extent = Vec2::new((borders.2 - borders.0) as f32, (borders.3 - borders.1) as f32) / self.zoom;
+        self.pan_by(offset * extent * Camera::PAN_STEP, borders);
+    }
+
+    pub fn reset(&mut self) {
+        self.zoom = 1.0;
+        self.pan = Vec2::ZERO;
+        self.follow = false;
+    }
+
+    pub fn update(&mut self, borders: (i32, i32, i32, i32), head: Option<(i32, i32)>, dt: f32) {
+        let target = match head {
+            Some((x, y)) if self.follow => Vec2::new(x as f32 + 0.5, y as f32 + 0.5),
+            _ => Camera::board_center(borders) + self.pan,
+        };
+        self.position += (target - self.position) * (1.0 - (-Camera::SMOOTHING * dt).exp());
+    }
+}
@@ -0,0 +1,107 @@
+use std::{fs::{self, File}, io::BufWriter, path::PathBuf};
+
+use clap::Args;
+use image::{codecs::gif::{GifEncoder, Repeat}, Delay, Frame};
+
+use crate::{raster, snake::{Rules, Snake}, theme::Theme};
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Output directory for a PNG sequence, or a path ending in .gif
+    out: PathBuf,
+    /// Seed for the Hamiltonian cycle, apples and power-ups
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Board width in cells, without the border
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(4..))]
+    width: u32,
+    /// Board height in cells, without the border
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(4..))]
+    height: u32,
+    /// Maximum number of frames to write
+    #[arg(long, default_value_t = 1000)]
+    frames: u32,
+    /// Game steps between two frames
+    #[arg(long, default_value_t = 1)]
+    skip: u32,
+    /// Pixels per board cell
+    #[arg(long, default_value_t = 16)]
+    cell: u32,
+    /// Delay between GIF frames in milliseconds
+    #[arg(long, default_value_t = 40)]
+    delay: u32,
+    /// Theme name, see the themes in the game
+    #[arg(long)]
+    theme: Option<String>,
+    /// Draw the Hamiltonian cycle
+    #[arg(long)]
+    cycle: bool,
+    /// Spawn power-ups
+    #[arg(long)]
+    power_ups: bool,
+}
+
+enum Output {
+    Frames(PathBuf),
+    Gif(GifEncoder<BufWriter<File>>),
+}
+
+impl Output {
+    fn write(&mut self, index: u32, image: image::RgbaImage, delay: u32) -> Result<(), String> {
+        match self {
+            Output::Frames(dir) => {
+                let path = dir.join(format!("frame_{:05}.png", index));
+                image.save(&path).map_err(|e| format!("Could not write {}: {}", path.display(), e))
+            }
+            Output::Gif(encoder) => encoder
+                .encode_frame(Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(delay, 1)))
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+pub fn run(args: ExportArgs) -> Result<(), String> {
+    let theme = match &args.theme {
+        Some(name) => Theme::load_all()
+            .into_iter()
+            .find(|theme| theme.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown theme {}", name))?,
+        None => Theme::default(),
+    };
+    let rules = Rules { power_ups: args.power_ups, ..Rules::default() };
+    let mut snake = Snake::with_seed(Snake::board_borders((args.width, args.height)), rules, args.seed);
+
+    let mut output = if args.out.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif")) {
+        let file = File::create(&args.out).map_err(|e| format!("Could not create {}: {}", args.out.display(), e))?;
+        let mut encoder = GifEncoder::new(BufWriter::new(file));
+        encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+        Output::Gif(encoder)
+    } else {
+        fs::create_dir_all(&args.out).map_err(|e| format!("Could not create {}: {}", args.out.display(), e))?;
+        Output::Frames(args.out.clone())
+    };
+
+    let progress = indicatif::ProgressBar::new(args.frames as u64);
+    let mut alive = true;
+    let mut written = 0;
+    while alive && written < args.frames {
+        output.write(written, raster::render(&snake, &theme, args.cell, args.cycle), args.delay)?;
+        written += 1;
+        progress.inc(1);
+        for _ in 0..args.skip.max(1) {
+            snake.direction = snake.bot_move();
+            if !snake.step() {
+                alive = false;
+                break;
+            }
+        }
+    }
+    if !alive && written < args.frames {
+        output.write(written, raster::render(&snake, &theme, args.cell, args.cycle), args.delay)?;
+        written += 1;
+    }
+    progress.finish_and_clear();
+
+    println!("Wrote {} frames to {} (length {}, {} steps)", written, args.out.display(), snake.body.len(), snake.steps);
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use std::{fs, path::PathBuf};
+
+use clap::Args;
+
+use crate::snake::{Rules, Snake, Undo};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HeatmapKind {
+    Visits,
+    Apples,
+    Deaths,
+}
+
+impl HeatmapKind {
+    pub const ALL: [HeatmapKind; 3] = [HeatmapKind::Visits, HeatmapKind::Apples, HeatmapKind::Deaths];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HeatmapKind::Visits => "visits",
+            HeatmapKind::Apples => "apples",
+            HeatmapKind::Deaths => "deaths",
+        }
+    }
+
+    pub fn next(kind: Option<HeatmapKind>) -> Option<HeatmapKind> {
+        match kind {
+            None => Some(HeatmapKind::Visits),
+            Some(HeatmapKind::Visits) => Some(HeatmapKind::Apples),
+            Some(HeatmapKind::Apples) => Some(HeatmapKind::Deaths),
+            Some(HeatmapKind::Deaths) => None,
+        }
+    }
+}
+
+pub struct Heatmap {
+    pub borders: (i32, i32, i32, i32),
+    pub games: u32,
+    visits: Vec<u32>,
+    apples: Vec<u32>,
+    deaths: Vec<u32>,
+}
+
+impl Heatmap {
+    pub fn new(borders: (i32, i32, i32, i32)) -> Self {
+        let cells = ((borders.2 - borders.0) * (borders.3 - borders.1)) as usize;
+        Heatmap {
+            borders,
+            games: 0,
+            visits: vec![0; cells],
+            apples: vec![0; cells],
+            deaths: vec![0; cells],
+        }
+    }
+
+    fn index(&self, (x, y): (i32, i32)) -> Option<usize> {
+        let (x0, y0, x1, y1) = self.borders;
+        if x < x0 || x >= x1 || y < y0 || y >= y1 {
+            return None;
+        }
+        Some(((y - y0) * (x1 - x0) + (x - x0)) as usize)
+    }
+
+    fn bump(&mut self, kind: HeatmapKind, cell: (i32, i32)) {
+        let Some(index) = self.index(cell) else {
+            return;
+        };
+        match kind {
+            HeatmapKind::Visits => self.visits[index] += 1,
+            HeatmapKind::Apples => self.apples[index] += 1,
+            HeatmapKind::Deaths => self.deaths[index] += 1,
+        }
+    }
+
+    fn values(&self, kind: HeatmapKind) -> &[u32] {
+        match kind {
+            HeatmapKind::Visits => &self.visits,
+            HeatmapKind::Apples => &self.apples,
+            HeatmapKind::Deaths => &self.deaths,
+        }
+    }
+
+    pub fn start_game(&mut self, snake: &Snake) {
+        if snake.borders != self.borders {
+            *self = Heatmap::new(snake.borders);
+        }
+        self.games += 1;
+        self.bump(HeatmapKind::Visits, *snake.body.front().unwrap());
+        self.bump(HeatmapKind::Apples, snake.apple);
+    }
+
+    pub fn record_step(&mut self, before: &Undo, after: &Snake) {
+        if after.borders != self.borders {
+            return;
+        }
+        self.bump(HeatmapKind::Visits, *after.body.front().unwrap());
+        if after.apples > before.apples || (after.apple != before.apple && after.apple_age == 0) {
+            self.bump(HeatmapKind::Apples, after.apple);
+        }
+    }
+
+    pub fn record_death(&mut self, snake: &Snake) {
+        if snake.borders == self.borders {
+            self.bump(HeatmapKind::Deaths, *snake.body.front().unwrap());
+        }
+    }
+
+    pub fn heat(&self, kind: HeatmapKind) -> impl Iterator<Item = ((i32, i32), f32)> + '_ {
+        let values = self.values(kind);
+        let max = values.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let (x0, y0, x1, _) = self.borders;
+        let width = x1 - x0;
+        values
+            .iter()
+            .enumerate()
+            .filter(|&(_, &value)| value > 0)
+            .map(move |(i, &value)| ((x0 + i as i32 % width, y0 + i as i32 / width), (value as f32 / max).sqrt()))
+    }
+
+    pub fn to_csv(&self, kind: HeatmapKind) -> String {
+        let width = (self.borders.2 - self.borders.0) as usize;
+        self.values(kind)
+            .chunks(width)
+            .rev()
+            .map(|row| row.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(","))
+            .collect::<Vec<_>>()
+            .join("\n")
+            + "\n"
+    }
+
+    pub fn save_csv(&self) -> Vec<PathBuf> {
+        HeatmapKind::ALL
+            .into_iter()
+            .filter_map(|kind| crate::storage::write(&format!("heatmap_{}.csv", kind.name()), &self.to_csv(kind)))
+            .collect()
+    }
+}
+
+#[derive(Args)]
+pub struct HeatmapArgs {
+    /// Directory for visits.csv, apples.csv and deaths.csv
+    out: PathBuf,
+    /// Number of bot games to simulate
+    #[arg(long, default_value_t = 10)]
+    games: u32,
+    /// Seed of the first game, following games use the next seeds
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Board width in cells, without the border
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(4..))]
+    width: u32,
+    /// Board height in cells, without the border
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(4..))]
+    height: u32,
+    /// Stop a game after this many steps
+    #[arg(long, default_value_t = 100_000)]
+    max_steps: u32,
+    /// Spawn power-ups
+    #[arg(long)]
+    power_ups: bool,
+}
+
+pub fn run(args: HeatmapArgs) -> Result<(), String> {
+    let borders = Snake::board_borders((args.width, args.height));
+    let rules = Rules { power_ups: args.power_ups, ..Rules::default() };
+    let mut heatmap = Heatmap::new(borders);
+    let progress = indicatif::ProgressBar::new(args.games as u64);
+    for game in 0..args.games {
+        let mut snake = Snake::with_seed(borders, rules, args.seed + game as u64);
+        heatmap.start_game(&snake);
+        while snake.steps < args.max_steps {
+            let before = snake.record();
+            snake.direction = snake.bot_move();
+            if !snake.step() {
+                if !snake.is_full() {
+                    snake.undo(&before);
+                    heatmap.record_death(&snake);
+                }
+                break;
+            }
+            heatmap.record_step(&before, &snake);
+        }
+        progress.inc(1);
+    }
+    progress.finish_and_clear();
+
+    fs::create_dir_all(&args.out).map_err(|e| format!("Could not create {}: {}", args.out.display(), e))?;
+    for kind in HeatmapKind::ALL {
+        let path = args.out.join(format!("{}.csv", kind.name()));
+        fs::write(&path, heatmap.to_csv(kind)).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    }
+    println!("Wrote heatmaps of {} games to {}", heatmap.games, args.out.display());
+    Ok(())
+}
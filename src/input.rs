@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use nannou::event::Key;
+use serde::Deserialize;
+
+use crate::snake::Direction;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Faster,
+    Slower,
+    BiggerBoard,
+    SmallerBoard,
+    ToggleBot,
+    Pause,
+    Restart,
+    Fullscreen,
+    PowerUps,
+    AppleRule,
+    Portals,
+    NextMode,
+    LevelSelect,
+    Select,
+    Help,
+    Debug,
+    Editor,
+    SavePosition,
+    LoadPosition,
+    StepBack,
+    StepForward,
+    Hud,
+    NextTheme,
+    PathOverlay,
+    ZoomIn,
+    ZoomOut,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    FollowCamera,
+    ResetCamera,
+    Replay,
+    Heatmap,
+    ExportHeatmap,
+    SaveGame,
+    LoadGame,
+}
+
+impl Action {
+    pub const ALL: [Action; 41] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Faster,
+        Action::Slower,
+        Action::BiggerBoard,
+        Action::SmallerBoard,
+        Action::ToggleBot,
+        Action::Pause,
+        Action::Restart,
+        Action::Fullscreen,
+        Action::PowerUps,
+        Action::AppleRule,
+        Action::Portals,
+        Action::NextMode,
+        Action::LevelSelect,
+        Action::Select,
+        Action::Help,
+        Action::Debug,
+        Action::Editor,
+        Action::SavePosition,
+        Action::LoadPosition,
+        Action::StepBack,
+        Action::StepForward,
+        Action::Hud,
+        Action::NextTheme,
+        Action::PathOverlay,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::FollowCamera,
+        Action::ResetCamera,
+        Action::Replay,
+        Action::Heatmap,
+        Action::ExportHeatmap,
+        Action::SaveGame,
+        Action::LoadGame,
+    ];
+
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::Faster | Action::Slower | Action::StepBack | Action::StepForward | Action::ZoomIn | Action::ZoomOut | Action::PanUp | Action::PanDown | Action::PanLeft | Action::PanRight
+        )
+    }
+
+    pub fn direction(self) -> Option<Direction> {
+        match self {
+            Action::Up => Some(Direction::Up),
+            Action::Down => Some(Direction::Down),
+            Action::Left => Some(Direction::Left),
+            Action::Right => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Up => "Turn up",
+            Action::Down => "Turn down",
+            Action::Left => "Turn left",
+            Action::Right => "Turn right",
+            Action::Faster => "Speed up",
+            Action::Slower => "Slow down",
+            Action::BiggerBoard => "Bigger board",
+            Action::SmallerBoard => "Smaller board",
+            Action::ToggleBot => "Toggle bot",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+            Action::Fullscreen => "Fullscreen",
+            Action::PowerUps => "Toggle power-ups",
+            Action::AppleRule => "Cycle apple rule",
+            Action::Portals => "Cycle portals",
+            Action::NextMode => "Next game mode",
+            Action::LevelSelect => "Campaign levels",
+            Action::Select => "Confirm",
+            Action::Help => "Show bindings",
+            Action::Debug => "Debug mode (click: apple, right click: wall)",
+            Action::Editor => "Position editor",
+            Action::SavePosition => "Save position",
+            Action::LoadPosition => "Load position",
+            Action::StepBack => "Step back",
+            Action::StepForward => "Single step",
+            Action::Hud => "Toggle HUD",
+            Action::NextTheme => "Next theme",
+            Action::PathOverlay => "Cycle path overlay",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::PanUp => "Pan up (or drag with middle mouse)",
+            Action::PanDown => "Pan down",
+            Action::PanLeft => "Pan left",
+            Action::PanRight => "Pan right",
+            Action::FollowCamera => "Camera follows head",
+            Action::ResetCamera => "Reset camera",
+            Action::Replay => "Watch replay after a game",
+            Action::Heatmap => "Cycle heatmap overlay",
+            Action::ExportHeatmap => "Export heatmaps as CSV",
+            Action::SaveGame => "Save game",
+            Action::LoadGame => "Load saved game",
+        }
+    }
+
+    fn default_keys(self) -> Vec<Key> {
+        match self {
+            Action::Up => vec![Key::W],
+            Action::Down => vec![Key::S],
+            Action::Left => vec![Key::A],
+            Action::Right => vec![Key::D],
+            Action::Faster => vec![Key::Up],
+            Action::Slower => vec![Key::Down],
+            Action::BiggerBoard => vec![Key::Right],
+            Action::SmallerBoard => vec![Key::Left],
+            Action::ToggleBot => vec![Key::Return],
+            Action::Pause => vec![Key::Space],
+            Action::Restart => vec![Key::R],
+            Action::Fullscreen => vec![Key::F11],
+            Action::PowerUps => vec![Key::P],
+            Action::AppleRule => vec![Key::E],
+            Action::Portals => vec![Key::O],
+            Action::NextMode => vec![Key::M],
+            Action::LevelSelect => vec![Key::L],
+            Action::Select => vec![Key::Return],
+            Action::Help => vec![Key::H, Key::F1],
+            Action::Debug => vec![Key::F3],
+            Action::Editor => vec![Key::F2],
+            Action::SavePosition => vec![Key::F5],
+            Action::LoadPosition => vec![Key::F9],
+            Action::StepBack => vec![Key::Comma],
+            Action::StepForward => vec![Key::Period],
+            Action::Hud => vec![Key::Tab],
+            Action::NextTheme => vec![Key::T],
+            Action::PathOverlay => vec![Key::V],
+            Action::ZoomIn => vec![Key::Equals, Key::NumpadAdd],
+            Action::ZoomOut => vec![Key::Minus, Key::NumpadSubtract],
+            Action::PanUp => vec![Key::Numpad8],
+            Action::PanDown => vec![Key::Numpad2],
+            Action::PanLeft => vec![Key::Numpad4],
+            Action::PanRight => vec![Key::Numpad6],
+            Action::FollowCamera => vec![Key::C],
+            Action::ResetCamera => vec![Key::Key0, Key::Numpad0],
+            Action::Replay => vec![Key::B],
+            Action::Heatmap => vec![Key::G],
+            Action::ExportHeatmap => vec![Key::F6],
+            Action::SaveGame => vec![Key::F7],
+            Action::LoadGame => vec![Key::F8],
+        }
+    }
+}
+
+const NAMED_KEYS: [Key; 83] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    Key::Up, Key::Down, Key::Left, Key::Right,
+    Key::Return, Key::Space, Key::Tab, Key::Back, Key::Escape, Key::Insert, Key::Delete, Key::Home, Key::End,
+    Key::PageUp, Key::PageDown, Key::Minus, Key::Equals, Key::Plus, Key::Comma, Key::Period, Key::Slash,
+    Key::Semicolon, Key::Apostrophe, Key::LBracket, Key::RBracket, Key::Backslash, Key::Grave,
+    Key::NumpadAdd, Key::NumpadSubtract, Key::NumpadEnter, Key::Numpad0, Key::Numpad2, Key::Numpad4, Key::Numpad6, Key::Numpad8,
+];
+
+pub fn key_name(key: Key) -> String {
+    format!("{:?}", key)
+}
+
+pub fn parse_key(name: &str) -> Option<Key> {
+    NAMED_KEYS.into_iter().find(|&key| key_name(key).eq_ignore_ascii_case(name))
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct KeyRepeat {
+    pub delay: f32,
+    pub interval: f32,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        KeyRepeat {
+            delay: 0.3,
+            interval: 0.08,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Deserialize, Default)]
+pub struct BindingsConfig {
+    repeat: Option<KeyRepeat>,
+    #[serde(flatten)]
+    keys: HashMap<String, Vec<String>>,
+}
+
+pub struct KeyBindings {
+    bindings: Vec<(Action, Vec<Key>)>,
+    pub repeat: KeyRepeat,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            bindings: Action::ALL.into_iter().map(|action| (action, action.default_keys())).collect(),
+            repeat: KeyRepeat::default(),
+        }
+    }
+}
+
+impl KeyBindings {
+    const FILE: &'static str = "bindings.toml";
+
+    pub fn load() -> Self {
+        let mut bindings = KeyBindings::default();
+        bindings.apply(&crate::storage::load_config(KeyBindings::FILE), KeyBindings::FILE);
+        bindings
+    }
+
+    pub fn apply(&mut self, config: &BindingsConfig, file: &str) {
+        if let Some(repeat) = config.repeat {
+            self.repeat = repeat;
+        }
+        for (name, keys) in &config.keys {
+            let Ok(action) = Action::deserialize(toml::Value::String(name.clone())) else {
+                eprintln!("Unknown action in {}: {}", file, name);
+                continue;
+            };
+            let keys = keys
+                .iter()
+                .filter_map(|key| parse_key(key).or_else(|| {
+                    eprintln!("Unknown key for {}: {}", name, key);
+                    None
+                }))
+                .collect();
+            self.bind(action, keys);
+        }
+    }
+
+    pub fn location() -> String {
+        crate::storage::config_file(KeyBindings::FILE).map_or(KeyBindings::FILE.to_string(), |path| path.display().to_string())
+    }
+
+    pub fn bind(&mut self, action: Action, keys: Vec<Key>) {
+        if let Some((_, bound)) = self.bindings.iter_mut().find(|(bound, _)| *bound == action) {
+            *bound = keys;
+        }
+    }
+
+    pub fn actions(&self, key: Key) -> impl Iterator<Item = Action> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> String {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map_or(String::new(), |(_, keys)| keys.iter().map(|&key| key_name(key)).collect::<Vec<_>>().join("/"))
+    }
+
+    pub fn describe(&self) -> Vec<(Action, String)> {
+        self.bindings
+            .iter()
+            .map(|(action, keys)| (*action, keys.iter().map(|&key| key_name(key)).collect::<Vec<_>>().join(", ")))
+            .collect()
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::snake::{Rules, Snake};
+
+pub struct Level {
+    pub name: &'static str,
+    pub map: &'static [&'static str],
+    pub target: u32,
+    pub speed: f32,
+}
+
+impl Level {
+    pub fn size(&self) -> u32 {
+        self.map.len() as u32 / 2 + 1
+    }
+
+    fn cells(&self, tile: char) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let half = self.map.len() as i32 / 2;
+        self.map
+            .iter()
+            .enumerate()
+            .flat_map(move |(row, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter(move |&(_, c)| c == tile)
+                    .map(move |(column, _)| (column as i32 - half, half - 1 - row as i32))
+            })
+    }
+
+    pub fn walls(&self) -> HashSet<(i32, i32)> {
+        self.cells('#').collect()
+    }
+
+    pub fn portals(&self) -> Vec<((i32, i32), (i32, i32))> {
+        ('1'..='9')
+            .filter_map(|tile| {
+                let mut cells = self.cells(tile);
+                Some((cells.next()?, cells.next()?))
+            })
+            .collect()
+    }
+
+    pub fn snake(&self, rules: Rules, seed: u64) -> Snake {
+        let size = self.size() as i32;
+        let mut snake = Snake::with_seed((-size, -size, size, size), rules, seed);
+        snake.set_walls(self.walls());
+        snake.set_portals(self.portals());
+        snake
+    }
+}
+
+pub const LEVELS: [Level; 6] = [
+    Level {
+        name: "Open Field",
+        map: &[
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+        ],
+        target: 15,
+        speed: 0.08,
+    },
+    Level {
+        name: "Pillars",
+        map: &[
+            "..........",
+            ".##....##.",
+            ".##....##.",
+            "..........",
+            "..........",
+            "..........",
+            "..........",
+            ".##....##.",
+            ".##....##.",
+            "..........",
+        ],
+        target: 20,
+        speed: 0.1,
+    },
+    Level {
+        name: "Corridors",
+        map: &[
+            "............",
+            "............",
+            "............",
+            ".####..####.",
+            "............",
+            "............",
+            "............",
+            "............",
+            ".####..####.",
+            "............",
+            "............",
+            "............",
+        ],
+        target: 25,
+        speed: 0.12,
+    },
+    Level {
+        name: "Rooms",
+        map: &[
+            "..............",
+            ".#####..#####.",
+            ".#..........#.",
+            ".#..........#.",
+            ".#..........#.",
+            ".#..........#.",
+            "..............",
+            "..............",
+            ".#..........#.",
+            ".#..........#.",
+            ".#..........#.",
+            ".#..........#.",
+            ".#####..#####.",
+            "..............",
+        ],
+        target: 35,
+        speed: 0.14,
+    },
+    Level {
+        name: "Maze",
+        map: &[
+            "................",
+            ".######..######.",
+            ".#............#.",
+            ".#.####..####.#.",
+            ".#.#........#.#.",
+            ".#.#........#.#.",
+            ".#.#........#.#.",
+            "................",
+            "................",
+            ".#.#........#.#.",
+            ".#.#........#.#.",
+            ".#.#........#.#.",
+            ".#.####..####.#.",
+            ".#............#.",
+            ".######..######.",
+            "................",
+        ],
+        target: 50,
+        speed: 0.16,
+    },
+    Level {
+        name: "Portals",
+        map: &[
+            "............",
+            ".1........2.",
+            "............",
+            "..###..###..",
+            "............",
+            "............",
+            "............",
+            "............",
+            "..###..###..",
+            "............",
+            ".2........1.",
+            "............",
+        ],
+        target: 40,
+        speed: 0.16,
+    },
+];
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Progress {
+    pub unlocked: usize,
+    pub best: Vec<u32>,
+}
+
+impl Progress {
+    const FILE: &'static str = "progress.toml";
+
+    pub fn load() -> Self {
+        crate::storage::load(Progress::FILE)
+    }
+
+    pub fn is_unlocked(&self, level: usize) -> bool {
+        level <= self.unlocked
+    }
+
+    pub fn best(&self, level: usize) -> u32 {
+        self.best.get(level).copied().unwrap_or(0)
+    }
+
+    pub fn record(&mut self, level: usize, length: u32, completed: bool) {
+        if self.best.len() <= level {
+            self.best.resize(level + 1, 0);
+        }
+        self.best[level] = self.best[level].max(length);
+        if completed {
+            self.unlocked = self.unlocked.max(level + 1);
+        }
+        crate::storage::save(Progress::FILE, self);
+    }
+}
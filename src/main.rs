@@ -1,560 +1,1662 @@
-use std::{collections::VecDeque, hint::unreachable_unchecked};
+use std::{cell::RefCell, collections::{HashSet, VecDeque}, sync::OnceLock};
 
-use nannou::{event::Update, glam::Vec2, rand::{self, thread_rng, Rng}, time::DurationF64, App, Frame};
-use rayon::iter::{ParallelBridge, ParallelIterator};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use nannou::{event::{Key, MouseButton, MouseScrollDelta, TouchPhase, Update}, glam::Vec2, rand::{thread_rng, Rng}, time::DurationF64, App, Frame};
+
+mod camera;
+mod export;
+mod heatmap;
+mod input;
+mod level;
+mod mesh;
+mod mode;
+mod raster;
+mod settings;
+mod snake;
+mod storage;
+mod theme;
+mod tui;
+use camera::{Camera, Layout};
+use heatmap::{Heatmap, HeatmapKind};
+use input::{Action, KeyBindings};
+use level::{Progress, LEVELS};
+use mesh::Mesh;
+use mode::{Highscores, Mode, Outcome, ScoreKey};
+use settings::{Settings, Watcher};
+use snake::{AppleRule, Cycle, Direction, Item, Position, Rules, Snake, State, Undo};
+use theme::{Color, Theme};
+
+#[derive(Parser)]
+#[command(about = "Snake with a Hamiltonian cycle bot", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    game: GameArgs,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, ValueEnum)]
+enum Controller {
+    Bot,
+    Player,
+}
+
+#[derive(Args, Clone)]
+struct GameArgs {
+    /// Board width in cells, without the border
+    #[arg(long, value_parser = clap::value_parser!(u32).range(4..))]
+    width: Option<u32>,
+    /// Board height in cells, without the border
+    #[arg(long, value_parser = clap::value_parser!(u32).range(4..))]
+    height: Option<u32>,
+    /// Initial speed, 1.0 moves 90 cells per second
+    #[arg(long)]
+    speed: Option<f32>,
+    /// Who steers the snake
+    #[arg(long, value_enum)]
+    controller: Option<Controller>,
+    /// Seed of the first game, following games use the next seeds
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Initial window width in pixels
+    #[arg(long)]
+    window_width: Option<u32>,
+    /// Initial window height in pixels
+    #[arg(long)]
+    window_height: Option<u32>,
+    /// Start in fullscreen
+    #[arg(long)]
+    fullscreen: bool,
+    /// Theme name, see the themes in the game
+    #[arg(long)]
+    theme: Option<String>,
+    /// How the Hamiltonian cycle is generated
+    #[arg(long, value_enum)]
+    cycle: Option<Cycle>,
+    /// Play one bot game without a window and print the result
+    #[arg(long)]
+    headless: bool,
+    /// Start a new game instead of resuming the saved one
+    #[arg(long)]
+    new_game: bool,
+}
+
+impl GameArgs {
+    fn settings(&self) -> Result<Settings, String> {
+        let mut settings = Settings::load();
+        self.apply(&mut settings);
+        settings.validate()?;
+        if self.headless && !settings.bot.enabled {
+            return Err("Headless games need the bot controller".to_string());
+        }
+        Ok(settings)
+    }
+
+    fn apply(&self, settings: &mut Settings) {
+        settings.rules.width = self.width.unwrap_or(settings.rules.width);
+        settings.rules.height = self.height.unwrap_or(settings.rules.height);
+        settings.speed.initial = self.speed.unwrap_or(settings.speed.initial);
+        if let Some(controller) = self.controller {
+            settings.bot.enabled = controller == Controller::Bot;
+        }
+        settings.window.width = self.window_width.unwrap_or(settings.window.width);
+        settings.window.height = self.window_height.unwrap_or(settings.window.height);
+        settings.window.fullscreen |= self.fullscreen;
+        if self.theme.is_some() {
+            settings.colors.theme = self.theme.clone();
+        }
+        settings.bot.cycle = self.cycle.unwrap_or(settings.bot.cycle);
+    }
+}
+
+static STARTUP: OnceLock<(Settings, GameArgs)> = OnceLock::new();
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play a seeded bot game without a window and write it as PNG frames or a GIF
+    Export(export::ExportArgs),
+    /// Simulate bot games and write head visit, apple spawn and death heatmaps as CSV
+    Heatmap(heatmap::HeatmapArgs),
+    /// Play or watch the bot in the terminal
+    Tui(GameArgs),
+}
 
 fn main() {
     rayon::ThreadPoolBuilder::new().build_global().unwrap();
 
-    nannou::app(Model::new)
-        .update(Model::update)
-        .loop_mode(nannou::LoopMode::refresh_sync())
-        .run();
+    let cli = Cli::parse();
+    let game = match &cli.command {
+        Some(Command::Tui(game)) => game,
+        _ => &cli.game,
+    };
+    let settings = match game.settings() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match cli.command {
+        Some(Command::Export(args)) => {
+            if let Err(e) = export::run(args) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Heatmap(args)) => {
+            if let Err(e) = heatmap::run(args) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Tui(game)) => {
+            if let Err(e) = tui::run(settings, game) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        None if cli.game.headless => Model::create(settings, cli.game, false).run_headless(),
+        None => {
+            STARTUP.get_or_init(|| (settings, cli.game));
+            nannou::app(Model::new)
+                .update(Model::update)
+                .loop_mode(nannou::LoopMode::refresh_sync())
+                .run();
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum PathOverlay {
+    Off,
+    Lines,
+    Gradient,
+    HeadToApple,
+}
+
+impl PathOverlay {
+    fn next(self) -> PathOverlay {
+        match self {
+            PathOverlay::Off => PathOverlay::Lines,
+            PathOverlay::Lines => PathOverlay::Gradient,
+            PathOverlay::Gradient => PathOverlay::HeadToApple,
+            PathOverlay::HeadToApple => PathOverlay::Off,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Screen {
+    Game,
+    LevelSelect,
+    Editor,
+    GameOver,
+    Replay,
+}
+
+struct GameEnd {
+    outcome: Outcome,
+    length: u32,
+    steps: u32,
+    time: f32,
+    new_highscore: bool,
+    rank: Option<usize>,
+    replay: Vec<Direction>,
+    replay_start: Snake,
+    final_snake: Snake,
+    replay_position: f32,
+    shown: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedGame {
+    speed: f32,
+    bot: bool,
+    bot_played: bool,
+    mode: Mode,
+    level: Option<usize>,
+    board: (u32, u32),
+    portals: u32,
+    elapsed: f32,
+    seed: Option<i64>,
+    snake: State,
+}
+
+struct BoardCache {
+    borders: (i32, i32, i32, i32),
+    walls: HashSet<(i32, i32)>,
+    theme: Theme,
+    mesh: Mesh,
 }
 
 struct Model {
+    screen: Screen,
     snake: Snake,
+    direction_queue: VecDeque<Direction>,
+    history: VecDeque<Undo>,
+    future: Vec<Direction>,
     timer: f32,
+    elapsed: f32,
     bot: bool,
-    highscore: u32,
+    bot_played: bool,
+    end: Option<GameEnd>,
+    mode: Mode,
+    highscores: Highscores,
     running: bool,
     speed: f32,
-    size: u32,
-    key_cooldown: f32,
+    board: (u32, u32),
+    seed: Option<u64>,
+    rules: Rules,
+    portals: u32,
+    level: Option<usize>,
+    selected_level: usize,
+    progress: Progress,
+    bindings: KeyBindings,
+    themes: Vec<Theme>,
+    theme: usize,
+    path_overlay: PathOverlay,
+    heatmap: Heatmap,
+    heatmap_overlay: Option<HeatmapKind>,
+    show_help: bool,
+    show_hud: bool,
+    debug: bool,
+    keys_down: HashSet<Key>,
+    held: Vec<(Action, Key, f32)>,
+    camera: Camera,
+    drag: Option<Vec2>,
+    board_cache: RefCell<Option<BoardCache>>,
+    settings: Settings,
+    overrides: GameArgs,
+    watcher: Watcher,
 }
 
 impl Model {
     fn new(app: &nannou::App) -> Self {
-        app.new_window()
-            .size(800, 800)
+        let (settings, game) = STARTUP.get().unwrap();
+        let window = app.new_window().size(settings.window.width, settings.window.height);
+        let window = if settings.window.fullscreen { window.fullscreen() } else { window };
+        window
             .view(Model::view)
+            .key_pressed(key_pressed)
+            .key_released(key_released)
+            .mouse_pressed(mouse_pressed)
+            .mouse_released(mouse_released)
+            .mouse_moved(mouse_moved)
+            .mouse_wheel(mouse_wheel)
             .build()
             .unwrap();
 
-        Model {
-            snake: Snake::new((-6, -6, 6, 6)),
+        Model::create(settings.clone(), game.clone(), !game.new_game)
+    }
+
+    fn create(settings: Settings, overrides: GameArgs, resume: bool) -> Self {
+        let seed = overrides.seed;
+        let board = (settings.rules.width, settings.rules.height);
+        let rules = settings.rules();
+        let board_options = overrides.width.is_some() || overrides.height.is_some() || seed.is_some();
+        let saved = if !resume {
+            None
+        } else if board_options {
+            if storage::data_file(Model::SAVE_FILE).is_some_and(|path| path.exists()) {
+                println!("Starting a new game for the board options, the saved game is kept");
+            }
+            None
+        } else {
+            Model::read_saved_game()
+        };
+        let snake = match &saved {
+            Some((_, snake)) => snake.clone(),
+            None => {
+                let first_seed = seed.unwrap_or_else(|| thread_rng().gen());
+                let mut snake = Snake::with_seed(Snake::board_borders(board), rules, first_seed);
+                snake.add_random_portals(settings.rules.portals);
+                snake
+            }
+        };
+        let themes = settings.themes().unwrap_or_else(|_| Theme::load_all());
+        let theme = settings.theme_index(&themes);
+        let mut bindings = KeyBindings::load();
+        bindings.apply(&settings.keys, Settings::FILE);
+        let mut heatmap = Heatmap::new(snake.borders);
+        heatmap.start_game(&snake);
+        let mut model = Model {
+            screen: Screen::Game,
+            snake,
+            direction_queue: VecDeque::new(),
+            history: VecDeque::new(),
+            future: Vec::new(),
             timer: 0.0,
-            bot: true,
-            highscore: 0,
+            elapsed: 0.0,
+            bot: settings.bot.enabled,
+            bot_played: false,
+            end: None,
+            mode: settings.rules.mode,
+            highscores: Highscores::load(),
             running: true,
-            speed: 0.1,
-            size: 6,
-            key_cooldown: 0.0,
+            speed: settings.speed.initial,
+            board,
+            seed: seed.map(|seed| seed.wrapping_add(1)),
+            rules,
+            portals: settings.rules.portals,
+            level: None,
+            selected_level: 0,
+            progress: Progress::load(),
+            bindings,
+            themes,
+            theme: theme.unwrap_or(0),
+            path_overlay: PathOverlay::Lines,
+            heatmap,
+            heatmap_overlay: None,
+            show_help: false,
+            show_hud: true,
+            debug: false,
+            keys_down: HashSet::new(),
+            held: Vec::new(),
+            camera: Camera::default(),
+            drag: None,
+            board_cache: RefCell::new(None),
+            settings,
+            overrides,
+            watcher: Watcher::new(),
+        };
+        if let Some((game, snake)) = saved {
+            model.resume(game, snake);
+            storage::remove(Model::SAVE_FILE);
+            println!("Resumed and removed the saved game, {} saves it again", model.bindings.keys(Action::SaveGame));
         }
+        model
+    }
 
+    fn resize_board(&mut self, delta: i32) {
+        let resize = |cells: u32| (cells as i32 + delta).max(Model::MIN_BOARD.min(cells as i32)) as u32;
+        self.board = (resize(self.board.0), resize(self.board.1));
     }
 
-    pub fn update(app: &App, model: &mut Model, update: Update) {
-        handle_keyboard_input(model, update, app);
-        
-        if !model.running {
+    fn next_seed(&mut self) -> u64 {
+        match self.seed {
+            Some(seed) => {
+                self.seed = Some(seed.wrapping_add(1));
+                seed
+            }
+            None => thread_rng().gen(),
+        }
+    }
+
+    fn new_snake(&mut self) -> Snake {
+        let seed = self.next_seed();
+        match self.level {
+            Some(level) => LEVELS[level].snake(self.rules, seed),
+            None => {
+                let mut snake = Snake::with_seed(Snake::board_borders(self.board), self.rules, seed);
+                snake.add_random_portals(self.portals);
+                snake
+            }
+        }
+    }
+
+    const MIN_BOARD: i32 = 6;
+    const DIRECTION_BUFFER: usize = 3;
+    const HISTORY_LEN: usize = 5000;
+    const STALL_FACTOR: u32 = 100;
+    const CORNER_SAMPLES: usize = 6;
+
+    fn new_game(&mut self) {
+        self.snake = self.new_snake();
+        self.heatmap.start_game(&self.snake);
+        self.history.clear();
+        self.future.clear();
+        self.direction_queue.clear();
+        self.elapsed = 0.0;
+        self.bot_played = false;
+    }
+
+    fn restart(&mut self) {
+        self.end = None;
+        self.screen = Screen::Game;
+        self.running = true;
+        self.new_game();
+        self.timer = -self.settings.speed.start_delay;
+    }
+
+    fn queue_direction(&mut self, direction: Direction) {
+        let last = self.direction_queue.back().copied().unwrap_or(self.snake.direction);
+        if direction == last || (direction == last.opposite() && self.snake.body.len() > 1) {
             return;
         }
-        let step_time = 1.0 / 90 as f32 / model.speed;
-        model.timer += update.since_last.secs() as f32;
+        if self.direction_queue.len() < Model::DIRECTION_BUFFER {
+            self.direction_queue.push_back(direction);
+        }
+    }
+
+    fn finish(&mut self, outcome: Outcome) {
+        let length = self.snake.body.len() as u32;
+        let (new_highscore, rank) = match (self.level, self.mode) {
+            (Some(level), _) => {
+                let best = self.progress.best(level);
+                if !self.bot_played {
+                    self.progress.record(level, length, outcome == Outcome::LevelComplete);
+                }
+                (length > best, None)
+            }
+            (None, Mode::Sprint) if outcome != Outcome::SprintDone => (false, None),
+            (None, mode) => {
+                let rank = self.submit_score(match mode {
+                    Mode::Endless | Mode::Survival => length as f32,
+                    Mode::TimeAttack => self.snake.apples as f32,
+                    Mode::Sprint => self.elapsed,
+                });
+                (rank == Some(0), rank)
+            }
+        };
+        let mut replay_start = self.snake.clone();
+        let mut replay = Vec::with_capacity(self.history.len());
+        for undo in self.history.iter().rev() {
+            replay.push(replay_start.direction);
+            replay_start.undo(undo);
+        }
+        replay.reverse();
+        self.end = Some(GameEnd {
+            outcome,
+            length,
+            steps: self.snake.steps,
+            time: self.elapsed,
+            new_highscore,
+            rank,
+            replay,
+            replay_start,
+            final_snake: self.snake.clone(),
+            replay_position: 0.0,
+            shown: 0.0,
+        });
+        self.direction_queue.clear();
+        self.screen = Screen::GameOver;
+    }
+
+    fn effective_speed(&self) -> f32 {
+        let speed = self.speed * self.mode.speed_multiplier(self.snake.apples);
+        if self.snake.slow_motion > 0 { speed / 2.0 } else { speed }
+    }
 
-        if model.speed < 1.0 {
-            if model.timer < step_time {
+    fn advance(&mut self) {
+        if self.history.len() == Model::HISTORY_LEN {
+            self.history.pop_front();
+        }
+        let before = self.snake.record();
+        self.future.clear();
+
+        if self.bot {
+            self.bot_played = true;
+            self.snake.direction = self.snake.bot_move();
+        } else if let Some(direction) = self.direction_queue.pop_front() {
+            self.snake.direction = direction;
+        }
+        if !self.snake.step() {
+            let full = self.snake.is_full();
+            self.snake.undo(&before);
+            if full {
+                self.finish(Outcome::BoardFull);
+            } else {
+                self.heatmap.record_death(&self.snake);
+                self.finish(Outcome::Died);
+            }
+        } else {
+            self.heatmap.record_step(&before, &self.snake);
+            self.history.push_back(before);
+        }
+    }
+
+    fn step_forward(&mut self) {
+        self.running = false;
+        match self.future.pop() {
+            Some(direction) => {
+                let before = self.snake.record();
+                self.snake.direction = direction;
+                if self.snake.step() {
+                    self.history.push_back(before);
+                } else {
+                    self.snake.undo(&before);
+                    self.future.clear();
+                }
+            }
+            None => {
+                self.advance();
+                self.check_level_complete();
+                self.check_mode_finished();
+            }
+        }
+    }
+
+    fn step_back(&mut self) {
+        self.running = false;
+        if let Some(undo) = self.history.pop_back() {
+            self.future.push(self.snake.direction);
+            self.snake.undo(&undo);
+        }
+    }
+
+    fn score_key(&self) -> ScoreKey {
+        ScoreKey {
+            width: (self.snake.borders.2 - self.snake.borders.0 - 2) as u32,
+            height: (self.snake.borders.3 - self.snake.borders.1 - 2) as u32,
+            mode: self.mode,
+            bot: self.bot_played,
+        }
+    }
+
+    fn submit_score(&mut self, score: f32) -> Option<usize> {
+        let key = self.score_key();
+        let rank = self.highscores.submit(key, score);
+        match rank {
+            Some(rank) => println!("{}: {} (rank {})", key.describe(), self.mode.format_score(score), rank + 1),
+            None => println!("{}: {}", key.describe(), self.mode.format_score(score)),
+        }
+        rank
+    }
+
+    fn check_mode_finished(&mut self) {
+        if self.level.is_some() || self.screen != Screen::Game {
+            return;
+        }
+        let finished = match self.mode {
+            Mode::TimeAttack => self.elapsed >= Mode::TIME_ATTACK_SECONDS,
+            Mode::Sprint => self.snake.body.len() as u32 >= Mode::SPRINT_LENGTH,
+            Mode::Endless | Mode::Survival => false,
+        };
+        if finished {
+            self.finish(if self.mode == Mode::Sprint { Outcome::SprintDone } else { Outcome::TimeUp });
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.level = None;
+        self.new_game();
+        self.timer = -self.settings.speed.start_delay;
+    }
+
+    fn start_level(&mut self, level: Option<usize>) {
+        self.level = level;
+        if let Some(level) = level {
+            self.speed = LEVELS[level].speed;
+        }
+        self.new_game();
+        self.screen = Screen::Game;
+        self.timer = -self.settings.speed.start_delay;
+    }
+
+    fn check_level_complete(&mut self) {
+        let Some(level) = self.level else {
+            return;
+        };
+        if self.screen != Screen::Game || (self.snake.body.len() as u32) < LEVELS[level].target {
+            return;
+        }
+        if self.bot_played {
+            self.finish(Outcome::LevelComplete);
+        } else {
+            self.progress.record(level, self.snake.body.len() as u32, true);
+            if level + 1 < LEVELS.len() {
+                self.start_level(Some(level + 1));
+            } else {
+                self.selected_level = level;
+                self.screen = Screen::LevelSelect;
+            }
+        }
+    }
+
+    pub fn update(app: &App, model: &mut Model, update: Update) {
+        let dt = update.since_last.secs() as f32;
+        if let Some(previous) = model.reload_settings(dt) {
+            let window = &model.settings.window;
+            if window.width != previous.window.width || window.height != previous.window.height {
+                app.main_window().set_inner_size_points(window.width as f32, window.height as f32);
+            }
+            if window.fullscreen != previous.window.fullscreen {
+                app.main_window().set_fullscreen(window.fullscreen);
+            }
+        }
+        model.tick(dt);
+    }
+
+    fn reload_settings(&mut self, dt: f32) -> Option<Settings> {
+        let overrides = &self.overrides;
+        let settings = self.watcher.poll(dt, |settings| overrides.apply(settings))?;
+        let previous = std::mem::replace(&mut self.settings, settings);
+        let settings = &self.settings;
+        if settings.rules.width != previous.rules.width || settings.rules.height != previous.rules.height {
+            self.board = (settings.rules.width, settings.rules.height);
+        }
+        if settings.rules.portals != previous.rules.portals {
+            self.portals = settings.rules.portals;
+        }
+        if settings.rules() != previous.rules() {
+            self.rules = settings.rules();
+            self.snake.rules = self.rules;
+        }
+        if settings.speed.initial != previous.speed.initial {
+            self.speed = settings.speed.initial;
+        }
+        if settings.bot.enabled != previous.bot.enabled {
+            self.bot = settings.bot.enabled;
+        }
+        if settings.colors != previous.colors {
+            let name = self.theme().name.clone();
+            self.themes = settings.themes().unwrap_or_else(|_| Theme::load_all());
+            self.theme = settings.theme_index(&self.themes)
+                .or_else(|| self.themes.iter().position(|theme| theme.name == name))
+                .unwrap_or(0);
+        }
+        if settings.keys != previous.keys {
+            self.bindings = KeyBindings::load();
+            self.bindings.apply(&settings.keys, Settings::FILE);
+            self.held.clear();
+        }
+        if settings.rules.mode != previous.rules.mode {
+            self.set_mode(settings.rules.mode);
+        }
+        println!("Applied {}", Settings::location());
+        Some(previous)
+    }
+
+    fn tick(&mut self, dt: f32) {
+        self.repeat_held(dt);
+        self.camera.update(self.snake.borders, self.snake.body.front().copied(), dt);
+        match self.screen {
+            Screen::GameOver => {
+                if let Some(end) = self.end.as_mut() {
+                    end.shown += dt;
+                    if self.bot && end.shown >= self.settings.bot.restart_delay {
+                        self.restart();
+                    }
+                }
                 return;
             }
+            Screen::Replay => {
+                self.tick_replay(dt);
+                return;
+            }
+            Screen::Game => {}
+            Screen::LevelSelect | Screen::Editor => return,
+        }
+        
+        if !self.running {
+            return;
+        }
+        let speed = self.effective_speed();
+        let step_time = 1.0 / 90.0 / speed;
+        self.timer += dt;
+        if self.timer > 0.0 {
+            self.elapsed += dt;
+        }
+        self.check_mode_finished();
+
+        if speed < 1.0 && self.timer < step_time {
+            return;
         }
 
-        if model.snake.body.len() == 0 {
-            model.snake = Snake::new((-(model.size as i32), -(model.size as i32), model.size as i32, model.size as i32));
-            model.timer = -10.0;
+        if self.snake.body.is_empty() {
+            self.new_game();
+            self.timer = -10.0;
         }
 
 
-        if model.bot {
-            for _ in 0..(model.speed as usize).max(1) {
-                let dir = Snake::bot_move(model);
-                model.snake.direction = dir;
-                if !model.snake.step() {
-                    model.snake = Snake::new((-(model.size as i32), -(model.size as i32), model.size as i32, model.size as i32));
-                    model.timer = -3.0;
+        if self.bot {
+            self.direction_queue.clear();
+            for _ in 0..(speed as usize).max(1) {
+                if self.screen != Screen::Game {
+                    break;
                 }
+                self.advance();
             }
         } else {
-            if !model.snake.step() {
-                model.snake = Snake::new((-(model.size as i32), -(model.size as i32), model.size as i32, model.size as i32));
-                model.timer = -3.0;
+            self.advance();
+        }
+        self.check_level_complete();
+        self.check_mode_finished();
+
+        self.timer -= step_time;
+    }
+
+    fn run_headless(&mut self) {
+        self.timer = 0.0;
+        let mut last_apple = (self.snake.apples, self.snake.steps);
+        while self.screen == Screen::Game {
+            self.elapsed += 1.0 / 90.0 / self.effective_speed();
+            self.advance();
+            self.check_level_complete();
+            self.check_mode_finished();
+            if self.snake.apples != last_apple.0 {
+                last_apple = (self.snake.apples, self.snake.steps);
+            } else if self.snake.steps.saturating_sub(last_apple.1) > Model::STALL_FACTOR * self.snake.playable_cells() as u32 {
+                println!("Stalled: length {}, {} steps without an apple, {:.1}s", self.snake.body.len(), self.snake.steps - last_apple.1, self.elapsed);
+                return;
             }
         }
+        if let Some(end) = &self.end {
+            println!("{}: length {}, {} steps, {:.1}s", end.outcome.title(), end.length, end.steps, end.time);
+        }
+    }
 
-        if model.snake.body.len() as u32 > model.highscore {
-            model.highscore = model.snake.body.len() as u32;
-            println!("Highscore: {}", model.highscore);
+    fn tick_replay(&mut self, dt: f32) {
+        let Some(end) = self.end.as_mut() else {
+            self.screen = Screen::GameOver;
+            return;
+        };
+        if self.running {
+            end.replay_position += dt * 90.0 * self.speed;
+        }
+        let index = (end.replay_position as usize).min(end.replay.len());
+        while ((self.snake.steps - end.replay_start.steps) as usize) < index {
+            self.snake.direction = end.replay[(self.snake.steps - end.replay_start.steps) as usize];
+            if !self.snake.step() {
+                break;
+            }
+        }
+        if index == end.replay.len() || (self.snake.steps - end.replay_start.steps) as usize != index {
+            self.snake = end.final_snake.clone();
+            self.screen = Screen::GameOver;
+            end.shown = 0.0;
         }
+    }
 
-        model.timer -= step_time;
+    fn start_replay(&mut self) {
+        if let Some(end) = self.end.as_mut() {
+            end.replay_position = 0.0;
+            self.snake = end.replay_start.clone();
+            self.running = true;
+            self.screen = Screen::Replay;
+        }
     }
 
     pub fn view(app: &App, model: &Model, frame: Frame) {
         let draw = app.draw();
+        let theme = model.theme();
+
+        draw.background().color(nannou::color::Rgba::from(theme.background));
 
-        draw.background().color(nannou::color::BLACK);
+        if model.screen == Screen::LevelSelect {
+            model.draw_level_select(&draw, app.window_rect());
+            if model.show_help {
+                model.draw_help(&draw, app.window_rect());
+            }
+            draw.to_frame(app, &frame).unwrap();
+            return;
+        }
 
-        let size = app.window_rect().w().min(app.window_rect().h());
+        let rect = app.window_rect();
+        let layout = model.layout(rect);
+        let sqare_size = layout.cell;
+        let mut board_cache = model.board_cache.borrow_mut();
+        let stale = board_cache.as_ref().is_none_or(|cache| {
+            cache.borders != model.snake.borders || cache.walls != model.snake.walls || cache.theme != *theme
+        });
+        if stale {
+            *board_cache = Some(BoardCache {
+                borders: model.snake.borders,
+                walls: model.snake.walls.clone(),
+                theme: theme.clone(),
+                mesh: model.board_mesh(),
+            });
+        }
+        if let Some(cache) = board_cache.as_ref() {
+            cache.mesh.draw(&layout.transform(&draw));
+        }
 
-        let sqare_size = size / (model.snake.borders.2 - model.snake.borders.0) as f32;
-        for i in model.snake.borders.0..model.snake.borders.2 {
-            for j in model.snake.borders.1..model.snake.borders.3 {
-                let (x, y) = model.to_screen_coords(size, i, j);
-                if i == model.snake.borders.0 || i == model.snake.borders.2 - 1 || j == model.snake.borders.1 || j == model.snake.borders.3 - 1 {
-                    let color = nannou::color::rgb(0.2, 0.4, 0.2);
-                    draw.rect()
-                        .x_y(x, y)
-                        .w_h(sqare_size * 0.9, sqare_size * 0.9)
-                        .z(0.0)
-                        .color(color);
-                } else {
-                    let color = if (i + j + size as i32 * size as i32) % 2 == 0 {
-                        nannou::color::rgb(0.02, 0.2, 0.02)
-                    } else {
-                        nannou::color::rgb(0.04, 0.1, 0.04)
-                    };
-                    draw.rect()
-                        .x_y(x, y)
-                        .w_h(sqare_size * 0.9, sqare_size * 0.9)
-                        .z(0.0)
-                        .color(color);
-                }
+        if let Some(kind) = model.heatmap_overlay {
+            let mut heat = Mesh::default();
+            for ((x, y), t) in model.heatmap.heat(kind) {
+                let rgb = nannou::color::Rgb::from(nannou::color::Hsv::new(240.0 * (1.0 - t), 0.9, 1.0));
+                heat.rect(Vec2::new(x as f32 + 0.5, y as f32 + 0.5), Vec2::splat(0.9), 0.1, Color::rgba(rgb.red, rgb.green, rgb.blue, 0.3 + 0.4 * t));
             }
+            heat.draw(&layout.transform(&draw));
+            draw.text(&format!("Heatmap: {} over {} games", kind.name(), model.heatmap.games))
+                .x_y(0.0, rect.bottom() + 40.0)
+                .w(rect.w())
+                .font_size(14)
+                .z(100.0)
+                .color(nannou::color::Rgba::from(theme.text));
         }
 
-        for i in 0..model.snake.direction_path.len() {
-            let current = model.snake.direction_path.get(i).unwrap();
-            let x = model.snake.borders.0 + 1 + i as i32 % (model.snake.borders.2 - model.snake.borders.0 - 2);
-            let y = model.snake.borders.1 + 1 + i as i32 / (model.snake.borders.2 - model.snake.borders.0 - 2);
-            let (next_x, next_y) = match current {
+        let path_edge = |i: usize| {
+            let (x, y) = model.snake.path_cell(i);
+            let next = match model.snake.direction_path[i] {
                 Direction::Up => (x, y + 1),
                 Direction::Down => (x, y - 1),
                 Direction::Left => (x - 1, y),
                 Direction::Right => (x + 1, y),
             };
-            let square_size = size;
-
-            let start = model.to_screen_coords(square_size, x, y);
-            let end = model.to_screen_coords(square_size, next_x, next_y);
-
-            draw.line()
-                .start(Vec2::new(start.0, start.1))
-                .end(Vec2::new(end.0, end.1))
-                .color(nannou::color::rgba(1.0, 1.0, 1.0, 0.3));
+            ((x, y), next)
+        };
+        let edges = match model.path_overlay {
+            PathOverlay::Off => Vec::new(),
+            PathOverlay::Lines => (0..model.snake.direction_path.len())
+                .map(|i| {
+                    let (start, end) = path_edge(i);
+                    (start, end, theme.path)
+                })
+                .collect(),
+            PathOverlay::Gradient => {
+                let order = model.snake.cycle_order();
+                (0..model.snake.direction_path.len())
+                    .map(|i| {
+                        let (start, end) = path_edge(i);
+                        let hue = nannou::color::Hsv::new(360.0 * order[i] as f32 / order.len() as f32, 0.8, 1.0);
+                        let rgb = nannou::color::Rgb::from(hue);
+                        (start, end, Color::rgba(rgb.red, rgb.green, rgb.blue, theme.path.a.max(0.5)))
+                    })
+                    .collect()
+            }
+            PathOverlay::HeadToApple => {
+                let cells = model.snake.path_to_apple();
+                cells.windows(2)
+                    .enumerate()
+                    .map(|(i, pair)| (pair[0], pair[1], Color { a: 1.0 - 0.7 * i as f32 / cells.len() as f32, ..theme.path }))
+                    .collect()
+            }
+        };
+        let mut path_mesh = Mesh::default();
+        for ((x, y), (next_x, next_y), color) in edges {
+            let start = layout.cell_center((x, y));
+            let end = layout.cell_center((next_x, next_y));
+            if mesh::visible(rect, (start + end) / 2.0, sqare_size) {
+                path_mesh.line(start, end, 1.0, 0.25, color);
+            }
         }
+        path_mesh.draw(&draw);
+
+        let points = model.interpolated_body(layout, model.step_fraction());
+        let len = points.last().unwrap().1 + 1.0;
+        let base = 0.55f32.powf(1.0 / len).max(0.94);
+        let size_mult = (len / 200.0).clamp(0.6, 0.87);
+        let alpha = if model.snake.ghost > 0 { 0.5 } else { 1.0 };
+        let mut snake_mesh = Mesh::default();
+        for pair in points.windows(2) {
+            let [(start, i), (end, next)] = [pair[0], pair[1]];
+            let width = base.powf(i) * sqare_size * size_mult;
+            if !mesh::visible(rect, start, width + sqare_size) {
+                continue;
+            }
 
-        let base = 0.55f32.powf(1.0 / model.snake.body.len() as f32).max(0.94);
-        let size_mult = (model.snake.body.len() as f32 / 200.0).max(0.6).min(0.87);
-        for i in 0..model.snake.body.len() as usize - 1 {
-            let (x, y) = model.snake.body.get(i).unwrap();
-            let (x, y) = model.to_screen_coords(size, *x, *y);
-            let (n_x, n_y) = model.snake.body.get(i + 1).unwrap();
-            let (n_x, n_y) = model.to_screen_coords(size, *n_x, *n_y);
+            let color = theme.snake_color((len - i - 1.0) / len).with_alpha(alpha);
+            snake_mesh.line(start, end, width * base.powf(next - i), len - i, color);
 
-            let min_green = 150;
-            let max_green = 255;
-            let green = min_green as f32 + (max_green - min_green) as f32 * ((model.snake.body.len() - i - 1) as f32 / model.snake.body.len() as f32);
-            let color = nannou::color::rgb(20, green as u8, 20);
-            let width = base.powi(i as i32) * sqare_size * size_mult;
+            let color = theme.snake_color((len - i) / len).with_alpha(alpha);
+            snake_mesh.circle(start, width / 2.0, len - i + 0.5, color);
+        }
+        let tail = points.last().unwrap().0;
+        let tail_size = base.powf(len - 1.0) * sqare_size;
+        let color = theme.snake_color(1.0 / len).with_alpha(alpha);
+        snake_mesh.circle(tail, tail_size * size_mult / 2.0, 1.0, color);
+        snake_mesh.draw(&draw);
 
+        for (i, &(a, b)) in model.snake.portals.iter().enumerate() {
+            let color = nannou::color::hsv(i as f32 / model.snake.portals.len() as f32 + 0.5, 0.8, 1.0);
+            let (a_x, a_y) = layout.cell_center(a).into();
+            let (b_x, b_y) = layout.cell_center(b).into();
             draw.line()
-                .start(Vec2::new(x, y))
-                .end(Vec2::new(n_x, n_y))
-                .color(color)
-                .z(model.snake.body.len() as f32 - i as f32 )
-                .stroke_weight(width * base);
-
-            let green = min_green as f32 + (max_green - min_green) as f32 * ((model.snake.body.len() - i) as f32 / model.snake.body.len() as f32);
-            let color = nannou::color::rgb(20, green as u8, 20);
-            draw.ellipse()
-                .x_y(x, y)
-                .w_h(width, width)
-                .z(model.snake.body.len() as f32 - i as f32 + 0.5)
-                .color(color);
-        }
-        let (x, y) = model.snake.body.back().unwrap();
-        let (x, y) = model.to_screen_coords(size, *x, *y);
-        let size = base.powi(model.snake.body.len() as i32 - 1) * sqare_size;
-        let min_green = 150;
-        let max_green = 255;
-        let green = min_green as f32 + (max_green - min_green) as f32 * ((1) as f32 / model.snake.body.len() as f32);
-        let color = nannou::color::rgb(20, green as u8, 20);
-        draw.ellipse()
-            .x_y(x, y)
-            .w_h(size * size_mult, size * size_mult)
-            .z(1.0)
-            .color(color);
+                .start(Vec2::new(a_x, a_y))
+                .end(Vec2::new(b_x, b_y))
+                .z(0.5)
+                .stroke_weight(sqare_size * 0.05)
+                .color(nannou::color::hsva(i as f32 / model.snake.portals.len() as f32 + 0.5, 0.8, 1.0, 0.2));
+            for (x, y) in [(a_x, a_y), (b_x, b_y)] {
+                draw.ellipse()
+                    .x_y(x, y)
+                    .w_h(sqare_size * 0.75, sqare_size * 0.75)
+                    .z(0.5)
+                    .no_fill()
+                    .stroke_weight(sqare_size * 0.12)
+                    .stroke(color);
+            }
+        }
 
+        let apple_alpha = if model.snake.rules.apple == AppleRule::Expiring {
+            let remaining = model.snake.apple_lifetime().saturating_sub(model.snake.apple_age);
+            if remaining < 10 && remaining.is_multiple_of(2) { 0.3 } else { 1.0 }
+        } else {
+            1.0
+        };
         draw.ellipse()
-            .x_y(model.snake.apple.0 as f32 * sqare_size + sqare_size / 2.0, model.snake.apple.1 as f32 * sqare_size + sqare_size / 2.0)
+            .xy(layout.cell_center(model.snake.apple))
             .w_h(sqare_size * 0.7, sqare_size * 0.7)
             .z(1.5)
-            .color(nannou::color::RED);
+            .color(nannou::color::Rgba::from(theme.apple.with_alpha(apple_alpha)));
+
+        if let Some((item, (x, y), lifetime)) = model.snake.item {
+            let (x, y) = layout.cell_center((x, y)).into();
+            let alpha = if lifetime < 20 && lifetime % 4 < 2 { 0.3 } else { 1.0 };
+            match item {
+                Item::Golden => {
+                    draw.ellipse()
+                        .x_y(x, y)
+                        .w_h(sqare_size * 0.8, sqare_size * 0.8)
+                        .z(1.5)
+                        .color(nannou::color::Rgba::from(theme.golden.with_alpha(alpha)));
+                }
+                Item::SlowMotion => {
+                    draw.ellipse()
+                        .x_y(x, y)
+                        .w_h(sqare_size * 0.7, sqare_size * 0.7)
+                        .z(1.5)
+                        .stroke_weight(sqare_size * 0.1)
+                        .stroke(nannou::color::Rgba::from(theme.slow_motion.lerp(theme.text, 0.7).with_alpha(alpha)))
+                        .color(nannou::color::Rgba::from(theme.slow_motion.with_alpha(alpha)));
+                }
+                Item::Shrink => {
+                    draw.rect()
+                        .x_y(x, y)
+                        .w_h(sqare_size * 0.5, sqare_size * 0.5)
+                        .rotate(std::f32::consts::FRAC_PI_4)
+                        .z(1.5)
+                        .color(nannou::color::Rgba::from(theme.shrink.with_alpha(alpha)));
+                }
+                Item::Ghost => {
+                    draw.ellipse()
+                        .x_y(x, y)
+                        .w_h(sqare_size * 0.7, sqare_size * 0.7)
+                        .z(1.5)
+                        .color(nannou::color::Rgba::from(theme.ghost.with_alpha(alpha)));
+                }
+            }
+        }
+
+        if model.screen == Screen::Editor {
+            let (x, y) = *model.snake.body.front().unwrap();
+            let (x, y) = layout.cell_center((x, y)).into();
+            let (dx, dy) = match model.snake.direction {
+                Direction::Up => (0.0, 1.0),
+                Direction::Down => (0.0, -1.0),
+                Direction::Left => (-1.0, 0.0),
+                Direction::Right => (1.0, 0.0),
+            };
+            draw.arrow()
+                .start(Vec2::new(x, y))
+                .end(Vec2::new(x + dx * sqare_size * 0.8, y + dy * sqare_size * 0.8))
+                .stroke_weight(sqare_size * 0.1)
+                .z(100.0)
+                .color(nannou::color::Rgba::from(theme.text));
+        }
+
+        if let Some(status) = model.status_line() {
+            let rect = app.window_rect();
+            draw.text(&status)
+                .x_y(0.0, rect.top() - 20.0)
+                .w(rect.w())
+                .font_size(18)
+                .z(100.0)
+                .color(nannou::color::Rgba::from(theme.text));
+        }
+
+        if model.show_hud {
+            model.draw_hud(&draw, app.window_rect(), app.fps());
+        }
+
+        if model.screen == Screen::GameOver {
+            model.draw_end_screen(&draw);
+        }
+
+        if model.debug {
+            let rect = app.window_rect();
+            draw.text("DEBUG  click: move apple  right click: toggle wall")
+                .x_y(0.0, rect.bottom() + 20.0)
+                .w(rect.w())
+                .font_size(14)
+                .z(100.0)
+                .color(nannou::color::rgb(1.0, 0.5, 0.2));
+        }
+
+        if model.show_help {
+            model.draw_help(&draw, app.window_rect());
+        }
 
         draw.to_frame(app, &frame).unwrap();
     }
 
-    fn to_screen_coords(&self, size: f32, x: i32, y: i32) -> (f32, f32) {
-        let sqare_size = size / (self.snake.borders.2 - self.snake.borders.0) as f32;
-        let x = x as f32 * sqare_size + sqare_size / 2.0;
-        let y = y as f32 * sqare_size + sqare_size / 2.0;
-        (x, y)
+    fn repeat_held(&mut self, dt: f32) {
+        let interval = self.bindings.repeat.interval.max(0.01);
+        let mut triggered = Vec::new();
+        for (action, _, timer) in &mut self.held {
+            *timer -= dt;
+            while *timer <= 0.0 {
+                triggered.push(*action);
+                *timer += interval;
+            }
+        }
+        for action in triggered {
+            self.trigger(action);
+        }
     }
 
+    const POSITION_FILE: &'static str = "position.toml";
+    const SAVE_FILE: &'static str = "savegame.toml";
 
-}
+    fn open_editor(&mut self) {
+        self.screen = Screen::Editor;
+        self.direction_queue.clear();
+    }
 
+    fn close_editor(&mut self) {
+        self.history.clear();
+        self.future.clear();
+        self.screen = Screen::Game;
+        self.running = true;
+        self.timer = 0.0;
+        self.elapsed = 0.0;
+    }
 
+    fn save_position(&self) {
+        storage::save(Model::POSITION_FILE, &self.snake.position());
+        println!("Position saved");
+    }
 
-fn handle_keyboard_input(model: &mut Model, update: Update, app: &App) {
-    model.key_cooldown -= update.since_last.secs() as f32;
-    app.keys.down.iter().for_each(|key| {
-        match key {
-            nannou::event::Key::W => {
-                model.snake.direction = Direction::Up;
-            }
-            nannou::event::Key::S => {
-                model.snake.direction = Direction::Down;
+    fn load_position(&mut self) {
+        let Some(position) = storage::try_load::<Position>(Model::POSITION_FILE) else {
+            eprintln!("No saved position found");
+            return;
+        };
+        if let Err(e) = position.check() {
+            eprintln!("Could not load {}: {}", Model::POSITION_FILE, e);
+            return;
+        }
+        self.level = None;
+        if position.borders != self.snake.borders {
+            self.board = ((position.borders.2 - position.borders.0 - 2) as u32, (position.borders.3 - position.borders.1 - 2) as u32);
+            let seed = self.next_seed();
+            self.snake = Snake::with_seed(position.borders, self.rules, seed);
+        }
+        self.snake.set_position(position);
+        self.open_editor();
+    }
+
+    fn save_game(&self) {
+        if self.screen != Screen::Game {
+            eprintln!("Only a running game can be saved");
+            return;
+        }
+        if let Err(e) = self.snake.position().check() {
+            eprintln!("The game was not saved, {}", e);
+            return;
+        }
+        let game = SavedGame {
+            speed: self.speed,
+            bot: self.bot,
+            bot_played: self.bot_played,
+            mode: self.mode,
+            level: self.level,
+            board: self.board,
+            portals: self.portals,
+            elapsed: self.elapsed,
+            seed: self.seed.map(|seed| seed as i64),
+            snake: self.snake.state(),
+        };
+        storage::save(Model::SAVE_FILE, &game);
+        println!("Game saved");
+    }
+
+    fn read_saved_game() -> Option<(SavedGame, Snake)> {
+        let game = storage::try_load::<SavedGame>(Model::SAVE_FILE)?;
+        match Snake::from_state(&game.snake) {
+            Ok(snake) => Some((game, snake)),
+            Err(e) => {
+                eprintln!("Could not load {}: {}", Model::SAVE_FILE, e);
+                None
             }
-            nannou::event::Key::A => {
-                model.snake.direction = Direction::Left;
+        }
+    }
+
+    fn resume(&mut self, game: SavedGame, snake: Snake) {
+        self.snake = snake;
+        self.rules = self.snake.rules;
+        self.speed = game.speed;
+        self.bot = game.bot;
+        self.bot_played = game.bot_played;
+        self.mode = game.mode;
+        self.level = game.level.filter(|&level| level < LEVELS.len());
+        self.board = game.board;
+        self.portals = game.portals;
+        self.elapsed = game.elapsed;
+        self.seed = game.seed.map(|seed| seed as u64);
+        self.end = None;
+        self.screen = Screen::Game;
+        self.history.clear();
+        self.future.clear();
+        self.direction_queue.clear();
+        self.running = true;
+        self.timer = -self.settings.speed.start_delay;
+    }
+
+    fn load_game(&mut self) {
+        match Model::read_saved_game() {
+            Some((game, snake)) => {
+                self.resume(game, snake);
+                println!("Game loaded");
             }
-            nannou::event::Key::D => {
-                model.snake.direction = Direction::Right;
+            None => eprintln!("No saved game found"),
+        }
+    }
+
+    fn trigger(&mut self, action: Action) {
+        match (action, self.screen) {
+            (Action::SavePosition, _) => self.save_position(),
+            (Action::LoadPosition, _) => self.load_position(),
+            (Action::SaveGame, _) => self.save_game(),
+            (Action::LoadGame, _) => self.load_game(),
+            (Action::ZoomIn, _) => self.camera.zoom_by(Camera::ZOOM_STEP),
+            (Action::ZoomOut, _) => self.camera.zoom_by(1.0 / Camera::ZOOM_STEP),
+            (Action::PanUp, _) => self.camera.nudge(Direction::Up, self.snake.borders),
+            (Action::PanDown, _) => self.camera.nudge(Direction::Down, self.snake.borders),
+            (Action::PanLeft, _) => self.camera.nudge(Direction::Left, self.snake.borders),
+            (Action::PanRight, _) => self.camera.nudge(Direction::Right, self.snake.borders),
+            (Action::FollowCamera, _) => self.camera.follow = !self.camera.follow,
+            (Action::ResetCamera, _) => self.camera.reset(),
+            (Action::Heatmap, _) => {
+                self.heatmap_overlay = HeatmapKind::next(self.heatmap_overlay);
+                println!("Heatmap: {}", self.heatmap_overlay.map_or("off", HeatmapKind::name));
             }
-            nannou::event::Key::Up => {
-                if model.key_cooldown > 0.0 {
-                    return;
+            (Action::ExportHeatmap, _) => {
+                for path in self.heatmap.save_csv() {
+                    println!("Saved {}", path.display());
                 }
-                model.speed *= 1.2;
-                model.key_cooldown = 0.1;
             }
-            nannou::event::Key::Down => {
-                if model.key_cooldown > 0.0 {
-                    return;
-                }
-                model.speed /= 1.2;
-                model.key_cooldown = 0.1;
+            (_, Screen::LevelSelect) => self.trigger_level_select(action),
+            (_, Screen::Editor) => self.trigger_editor(action),
+            (_, Screen::Game) => self.trigger_game(action),
+            (_, Screen::GameOver) => self.trigger_game_over(action),
+            (_, Screen::Replay) => self.trigger_replay(action),
+        }
+    }
+
+    fn trigger_game_over(&mut self, action: Action) {
+        match action {
+            Action::Restart | Action::Select | Action::Pause => self.restart(),
+            Action::BiggerBoard => {
+                self.resize_board(4);
+                self.restart();
             }
-            nannou::event::Key::Right => {
-                if model.key_cooldown > 0.0 {
-                    return;
-                }
-                model.size += 2;
-                model.snake = Snake::new((-(model.size as i32), -(model.size as i32), model.size as i32, model.size as i32));
-                model.key_cooldown = 0.1;
+            Action::SmallerBoard => {
+                self.resize_board(-4);
+                self.restart();
             }
-            nannou::event::Key::Left => {
-                if model.key_cooldown > 0.0 {
-                    return;
+            Action::Replay => self.start_replay(),
+            Action::LevelSelect => self.trigger_game(action),
+            Action::Help => self.show_help = !self.show_help,
+            Action::Hud => self.show_hud = !self.show_hud,
+            _ => {}
+        }
+    }
+
+    fn trigger_replay(&mut self, action: Action) {
+        match action {
+            Action::Replay | Action::Restart | Action::Select => {
+                if let Some(end) = self.end.as_mut() {
+                    self.snake = end.final_snake.clone();
+                    end.shown = 0.0;
                 }
-                model.size = (model.size - 2).max(4); 
+                self.running = true;
+                self.screen = Screen::GameOver;
+            }
+            Action::Pause => self.running = !self.running,
+            Action::Faster => self.speed *= self.settings.speed.step,
+            Action::Slower => self.speed /= self.settings.speed.step,
+            Action::Help => self.show_help = !self.show_help,
+            Action::Hud => self.show_hud = !self.show_hud,
+            Action::PathOverlay => self.path_overlay = self.path_overlay.next(),
+            _ => {}
+        }
+    }
 
-                model.snake = Snake::new((-(model.size as i32), -(model.size as i32), model.size as i32, model.size as i32));
-                model.key_cooldown = 0.1;
+    fn trigger_game(&mut self, action: Action) {
+        if let Some(direction) = action.direction() {
+            self.queue_direction(direction);
+            return;
+        }
+        match action {
+            Action::Faster => self.speed *= self.settings.speed.step,
+            Action::Slower => self.speed /= self.settings.speed.step,
+            Action::BiggerBoard => {
+                self.resize_board(4);
+                self.new_game();
             }
-            nannou::event::Key::Return => {
-                if model.key_cooldown > 0.0 {
-                    return;
-                }
-                model.bot = !model.bot;
-                model.key_cooldown = 0.2;
+            Action::SmallerBoard => {
+                self.resize_board(-4);
+                self.new_game();
             }
-            nannou::event::Key::Space => {
-                if model.key_cooldown > 0.0 {
-                    return;
-                }
-                model.running = !model.running;
-                model.key_cooldown = 0.2;
+            Action::ToggleBot => self.bot = !self.bot,
+            Action::Pause => self.running = !self.running,
+            Action::Restart => self.new_game(),
+            Action::NextMode => self.set_mode(self.mode.next()),
+            Action::PowerUps => {
+                self.rules.power_ups = !self.rules.power_ups;
+                self.snake.rules = self.rules;
             }
-            nannou::event::Key::R => {
-                if model.key_cooldown > 0.0 {
-                    return;
-                }
-                model.snake = Snake::new((-(model.size as i32), -(model.size as i32), model.size as i32, model.size as i32));
-                model.key_cooldown = 0.1;
+            Action::AppleRule => {
+                self.rules.apple = self.rules.apple.next();
+                self.snake.rules = self.rules;
+                self.snake.apple_age = 0;
+                println!("Apples: {}", self.rules.apple.name());
             }
-            nannou::event::Key::F11 => {
-                if model.key_cooldown > 0.0 {
-                    return;
+            Action::Portals => {
+                self.portals = (self.portals + 1) % 4;
+                if self.level.is_none() {
+                    self.new_game();
                 }
-                let win_id = app.window_id();
-                let window = app.window(win_id).unwrap();
-                window.set_fullscreen(!window.is_fullscreen());
-                model.key_cooldown = 0.1;
             }
+            Action::LevelSelect => {
+                self.selected_level = self.level.unwrap_or(self.progress.unlocked.min(LEVELS.len() - 1));
+                self.screen = Screen::LevelSelect;
+            }
+            Action::Help => self.show_help = !self.show_help,
+            Action::Hud => self.show_hud = !self.show_hud,
+            Action::PathOverlay => self.path_overlay = self.path_overlay.next(),
+            Action::NextTheme => {
+                self.theme = (self.theme + 1) % self.themes.len();
+                println!("Theme: {}", self.theme().name);
+            }
+            Action::Debug => self.debug = !self.debug,
+            Action::Editor => self.open_editor(),
+            Action::StepBack => self.step_back(),
+            Action::StepForward => self.step_forward(),
+            Action::Up | Action::Down | Action::Left | Action::Right | Action::Select | Action::Fullscreen | Action::SavePosition | Action::LoadPosition => {}
+            Action::ZoomIn | Action::ZoomOut | Action::PanUp | Action::PanDown | Action::PanLeft | Action::PanRight | Action::FollowCamera | Action::ResetCamera => {}
+            Action::Replay | Action::Heatmap | Action::ExportHeatmap | Action::SaveGame | Action::LoadGame => {}
+        }
+    }
+
+    fn trigger_editor(&mut self, action: Action) {
+        if let Some(direction) = action.direction() {
+            self.snake.direction = direction;
+            return;
+        }
+        match action {
+            Action::Editor | Action::Select => self.close_editor(),
+            Action::Restart => self.snake.body.truncate(1),
+            Action::Help => self.show_help = !self.show_help,
             _ => {}
         }
-    });
-}
+    }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+    fn trigger_level_select(&mut self, action: Action) {
+        match action {
+            Action::Up | Action::Faster => self.selected_level = self.selected_level.saturating_sub(1),
+            Action::Down | Action::Slower => self.selected_level = (self.selected_level + 1).min(LEVELS.len() - 1),
+            Action::Select if self.progress.is_unlocked(self.selected_level) => self.start_level(Some(self.selected_level)),
+            Action::LevelSelect => self.start_level(None),
+            Action::Help => self.show_help = !self.show_help,
+            _ => {}
+        }
+    }
 
-#[derive(Clone)]
-struct Snake {
-    body: VecDeque<(i32, i32)>,
-    direction: Direction,
-    borders: (i32, i32, i32, i32),
-    apple: (i32, i32),
-    direction_path: Vec<Direction>,
-}
+    fn hud_lines(&self, fps: f32) -> [String; 8] {
+        [
+            format!("Length {}", self.snake.body.len()),
+            format!("Best {}", self.highscores.best(&self.score_key()).map_or("-".to_string(), |score| self.mode.format_score(score))),
+            format!("Steps {}", self.snake.steps),
+            format!("Speed x{:.2}", self.effective_speed() / self.settings.speed.initial),
+            format!("Board {}x{}", self.snake.borders.2 - self.snake.borders.0 - 2, self.snake.borders.3 - self.snake.borders.1 - 2),
+            format!("Controller {}", if self.bot { "bot" } else { "player" }),
+            format!("Fill {:.1}%", 100.0 * self.snake.body.len() as f32 / self.snake.playable_cells() as f32),
+            format!("FPS {:.0}", fps),
+        ]
+    }
 
-impl Snake {
-    fn new(borders: (i32, i32, i32, i32)) -> Self {
-        let mut body = VecDeque::new();
-        body.push_back((0, 0));
-        Snake {
-            body,
-            direction: Direction::Up,
-            borders,
-            apple: (1, 1),
-            direction_path: Snake::init_path_direction((borders.2 - borders.0 - 2) as u32),
+    fn draw_hud(&self, draw: &nannou::Draw, rect: nannou::geom::Rect, fps: f32) {
+        let lines = self.hud_lines(fps);
+        let line_height = 18.0;
+        draw.rect()
+            .x_y(rect.left() + 100.0, rect.top() - 10.0 - lines.len() as f32 * line_height / 2.0)
+            .w_h(190.0, lines.len() as f32 * line_height + 10.0)
+            .z(150.0)
+            .color(nannou::color::rgba(0.0, 0.0, 0.0, 0.6));
+        for (i, line) in lines.iter().enumerate() {
+            draw.text(line)
+                .x_y(rect.left() + 100.0, rect.top() - 15.0 - (i as f32 + 0.5) * line_height)
+                .w(170.0)
+                .left_justify()
+                .font_size(14)
+                .z(151.0)
+                .color(nannou::color::rgb(0.9, 0.9, 0.9));
         }
     }
 
+    fn draw_help(&self, draw: &nannou::Draw, rect: nannou::geom::Rect) {
+        let bindings = self.bindings.describe();
+        let line_height = 22.0;
+        let fitting_rows = (((rect.h() - 120.0) / line_height) as usize).max(1);
+        let columns = bindings.len().div_ceil(fitting_rows);
+        let rows = bindings.len().div_ceil(columns);
+        let column_width = (rect.w() / columns as f32).min(420.0);
+        let width = column_width * columns as f32;
+        let height = (rows as f32 + 2.0) * line_height;
+        draw.rect()
+            .x_y(0.0, 0.0)
+            .w_h(width, height)
+            .z(200.0)
+            .color(nannou::color::rgba(0.0, 0.0, 0.0, 0.85));
+        for (i, (action, keys)) in bindings.iter().enumerate() {
+            let x = -width / 2.0 + ((i / rows) as f32 + 0.5) * column_width;
+            let y = height / 2.0 - ((i % rows) as f32 + 1.5) * line_height;
+            draw.text(action.description())
+                .x_y(x - column_width / 4.0, y)
+                .w(column_width / 2.0)
+                .left_justify()
+                .font_size(16)
+                .z(201.0)
+                .color(nannou::color::rgb(0.8, 0.8, 0.8));
+            draw.text(keys)
+                .x_y(x + column_width / 4.0, y)
+                .w(column_width / 2.0)
+                .left_justify()
+                .font_size(16)
+                .z(201.0)
+                .color(nannou::color::Rgba::from(self.theme().highlight));
+        }
+        draw.text(&format!("Rebind keys in {} or {}", KeyBindings::location(), Settings::location()))
+            .x_y(0.0, rect.bottom() + 30.0)
+            .w(rect.w())
+            .font_size(12)
+            .z(201.0)
+            .color(nannou::color::rgb(0.6, 0.6, 0.6));
+    }
 
-    fn init_path_direction(size: u32) -> Vec<Direction> {
-        let mut path = (0..size).flat_map(|y |
-            (0..size).map(move |x| {
-                if y == size - 1 && x > 0 {
-                    return Direction::Left;
-                }
-        
-                if x % 2 == 0 {
-                    if y == 0 {
-                        return Direction::Right;
-                    } else {
-                        return Direction::Down;
-                    }
-                } else {
-                    if y == size - 2 && x < size - 1 {
-                        return Direction::Right;
-                    } else {
-                        return Direction::Up;
-                    }
-                }
-            })
-        ).collect::<Vec<Direction>>();    
+    fn status_line(&self) -> Option<String> {
+        if let (Screen::Replay, Some(end)) = (self.screen, &self.end) {
+            return Some(format!("REPLAY   step {}/{}   {}: back", self.snake.steps, end.steps, self.bindings.keys(Action::Replay)));
+        }
+        if self.screen == Screen::Editor {
+            let controller = if self.bot { "bot" } else { "player" };
+            return Some(format!(
+                "EDITOR  click: body  right click: apple  middle click: wall  {}/{}/{}/{}: heading  {}: play as {}",
+                self.bindings.keys(Action::Up),
+                self.bindings.keys(Action::Down),
+                self.bindings.keys(Action::Left),
+                self.bindings.keys(Action::Right),
+                self.bindings.keys(Action::Editor),
+                controller,
+            ));
+        }
+        if !self.running {
+            return Some(format!("PAUSED   rewind {} / redo {}", self.history.len(), self.future.len()));
+        }
+        if let Some(level) = self.level {
+            return Some(format!("Level {}: {}   {}/{}", level + 1, LEVELS[level].name, self.snake.body.len(), LEVELS[level].target));
+        }
+        let best = self.highscores
+            .best(&self.score_key())
+            .map_or(String::new(), |score| format!("   best {}", self.mode.format_score(score)));
+        match self.mode {
+            Mode::Endless => None,
+            Mode::TimeAttack => Some(format!("{}   {:.1}s left   {} apples{}", self.mode.name(), (Mode::TIME_ATTACK_SECONDS - self.elapsed).max(0.0), self.snake.apples, best)),
+            Mode::Sprint => Some(format!("{}   {}/{}   {:.2}s{}", self.mode.name(), self.snake.body.len(), Mode::SPRINT_LENGTH, self.elapsed, best)),
+            Mode::Survival => Some(format!("{}   length {}   speed x{:.2}{}", self.mode.name(), self.snake.body.len(), self.mode.speed_multiplier(self.snake.apples), best)),
+        }
+    }
 
-        let num_iterations = (size * size) as u64;
-        
-        let progress = indicatif::ProgressBar::new(num_iterations);
-        progress.inc(0);
-        let offsets = [-1, -(size as i32) + 1, -(size as i32), -(size as i32) - 1, 1, size as i32 - 1, size as i32 + 1, size as i32];
-
-        for _ in 0..num_iterations {
-            progress.inc(1);
-            let tmp_path = (0..)
-                .par_bridge()
-                .map(|_| {
-                    let random_index_1 = thread_rng().gen_range(0..path.len());
-                    let random_index_2 = (random_index_1 as i32 + offsets[thread_rng().gen_range(0..8)]).min(path.len() as i32 - 1).max(0) as usize;
-                    let random_index_3 = thread_rng().gen_range(0..path.len());
-                    let random_index_4 = (random_index_3 as i32 + offsets[thread_rng().gen_range(0..8)]).min(path.len() as i32 - 1).max(0) as usize;
-
-                    let random_dir_1 = Snake::random_dir(path[random_index_1]);
-                    let random_dir_2 = Snake::random_dir(path[random_index_2]);
-                    let random_dir_3 = Snake::random_dir(path[random_index_3]);
-                    let random_dir_4 = Snake::random_dir(path[random_index_4]);
-                    (random_index_1, random_index_2, random_index_3, random_index_4, random_dir_1, random_dir_2, random_dir_3, random_dir_4)
-                })
-                .find_any(|(random_index1, random_index2, random_index3, random_index4, random_dir1, random_dir2, random_dir3, random_dir4)| {
-                    let mut x = 0;
-                    let mut y = 0;
-                    let mut path_len = 0;
-                    let mut seen: Vec<bool> = vec![false; path.len()];
-                    let mut new_path = path.clone();
-                    new_path[*random_index1] = *random_dir1;
-                    new_path[*random_index2] = *random_dir2;
-                    new_path[*random_index3] = *random_dir3;
-                    new_path[*random_index4] = *random_dir4;
-                    loop {
-                        if seen[x as usize + y as usize * size as usize] {
-                            break;
-                        }
-                        seen[x as usize + y as usize * size as usize] = true;
-                        match new_path[x as usize + y as usize * size as usize] {
-                            Direction::Up => y += 1,
-                            Direction::Down => y -= 1,
-                            Direction::Left => x -= 1,
-                            Direction::Right => x += 1,
-                        }
-                        path_len += 1;
-                        if (x == 0 && y == 0) || x < 0 || x >= size as i32 || y < 0 || y >= size as i32 {
-                            break;
-                        }
-                    }
-                    
-                    path_len == new_path.len() && x == 0 && y == 0
-                }).unwrap();
-            
-            let (random_index1, random_index2, random_index3, random_index4, random_dir1, random_dir2, random_dir3, random_dir4) = tmp_path;
-            path[random_index1] = random_dir1;
-            path[random_index2] = random_dir2;
-            path[random_index3] = random_dir3;
-            path[random_index4] = random_dir4;
+    fn level_entry(&self, level: usize) -> String {
+        if self.progress.is_unlocked(level) {
+            format!("{}. {}   target {}   best {}", level + 1, LEVELS[level].name, LEVELS[level].target, self.progress.best(level))
+        } else {
+            format!("{}. locked", level + 1)
         }
+    }
 
+    fn end_lines(&self) -> Vec<(String, bool)> {
+        let Some(end) = &self.end else {
+            return Vec::new();
+        };
+        let mut lines = vec![
+            (end.outcome.title().to_string(), true),
+            (format!("Length {}   Steps {}   Time {:.1}s", end.length, end.steps, end.time), false),
+        ];
+        if end.new_highscore {
+            lines.push(("New highscore!".to_string(), true));
+        }
+        if self.bot {
+            lines.push((format!("Restarting in {:.0}s", (self.settings.bot.restart_delay - end.shown).max(0.0).ceil()), false));
+        }
+        lines.push((format!(
+            "{}: restart   {}/{}: board size {}x{}   {}: replay",
+            self.bindings.keys(Action::Restart),
+            self.bindings.keys(Action::SmallerBoard),
+            self.bindings.keys(Action::BiggerBoard),
+            self.board.0,
+            self.board.1,
+            self.bindings.keys(Action::Replay),
+        ), false));
+        if self.level.is_none() {
+            let key = self.score_key();
+            lines.push((String::new(), false));
+            lines.push((format!("Top {}", key.describe()), false));
+            for (i, score) in self.highscores.table(&key).iter().enumerate() {
+                let name = score.name.as_deref().unwrap_or("");
+                lines.push((format!("{:>2}. {:<14} {}  {}", i + 1, self.mode.format_score(score.score), score.date, name), end.rank == Some(i)));
+            }
+        }
+        lines
+    }
 
-        path
+    fn draw_end_screen(&self, draw: &nannou::Draw) {
+        let lines = self.end_lines();
+        let line_height = 26.0;
+        let height = (lines.len() as f32 + 1.0) * line_height;
+        let theme = self.theme();
+        draw.rect()
+            .x_y(0.0, 0.0)
+            .w_h(520.0, height)
+            .z(180.0)
+            .color(nannou::color::rgba(0.0, 0.0, 0.0, 0.8));
+        for (i, (line, highlighted)) in lines.iter().enumerate() {
+            let size = if i == 0 { 28 } else { 16 };
+            let color = if *highlighted { theme.highlight } else { Color::rgb(0.9, 0.9, 0.9) };
+            draw.text(line)
+                .x_y(0.0, height / 2.0 - (i as f32 + 1.0) * line_height)
+                .w(500.0)
+                .font_size(size)
+                .z(181.0)
+                .color(nannou::color::Rgba::from(color));
+        }
     }
 
-    fn path_direction(&self, x: i32, y: i32) -> Direction {
-        self.direction_path[(y - self.borders.0 - 1) as usize * (self.borders.3 - self.borders.1 - 2) as usize + (x - self.borders.1 - 1) as usize]
+    fn draw_level_select(&self, draw: &nannou::Draw, rect: nannou::geom::Rect) {
+        let line_height = 40.0;
+        let top = (LEVELS.len() as f32 + 1.0) * line_height / 2.0;
+        draw.text("Campaign")
+            .x_y(0.0, top)
+            .w(rect.w())
+            .font_size(32)
+            .color(nannou::color::Rgba::from(self.theme().text));
+        for i in 0..LEVELS.len() {
+            let text = self.level_entry(i);
+            let color = if i == self.selected_level {
+                self.theme().highlight
+            } else if self.progress.is_unlocked(i) {
+                self.theme().text.with_alpha(0.8)
+            } else {
+                self.theme().text.with_alpha(0.4)
+            };
+            draw.text(&text)
+                .x_y(0.0, top - (i as f32 + 1.5) * line_height)
+                .w(rect.w())
+                .font_size(20)
+                .color(nannou::color::Rgba::from(color));
+        }
+        let hint = format!(
+            "{}/{}: select   {}: play   {}: back to endless",
+            self.bindings.keys(Action::Up),
+            self.bindings.keys(Action::Down),
+            self.bindings.keys(Action::Select),
+            self.bindings.keys(Action::LevelSelect),
+        );
+        draw.text(&hint)
+            .x_y(0.0, rect.bottom() + 30.0)
+            .w(rect.w())
+            .font_size(14)
+            .color(nannou::color::Rgba::from(self.theme().text.with_alpha(0.6)));
     }
 
-    fn free_path_len(&self, base_x: i32, base_y: i32, max_len: u32) -> u32 {
-        let (mut x, mut y) = (base_x, base_y);
-        let mut len = 0;
-        let snakelen = self.body.len();
-        let mut apples = 0;
-        loop {
-            match self.path_direction(x, y) {
-                Direction::Up => y += 1,
-                Direction::Down => y -= 1,
-                Direction::Left => x -= 1,
-                Direction::Right => x += 1,
+    fn theme(&self) -> &Theme {
+        &self.themes[self.theme]
+    }
+
+    fn step_fraction(&self) -> f32 {
+        let speed = self.effective_speed();
+        if !self.running || self.screen != Screen::Game || speed >= 1.0 {
+            return 1.0;
+        }
+        (self.timer * 90.0 * speed).clamp(0.0, 1.0)
+    }
+
+    fn interpolated_body(&self, layout: Layout, t: f32) -> Vec<(Vec2, f32)> {
+        let body = &self.snake.body;
+        let adjacent = |from: (i32, i32), to: (i32, i32)| (from.0 - to.0).abs() + (from.1 - to.1).abs() == 1;
+        if body.len() == 1 {
+            let head = match self.snake.last_tail.filter(|&last| adjacent(last, body[0])) {
+                Some(last) => layout.cell_center(last).lerp(layout.cell_center(body[0]), t),
+                None => layout.cell_center(body[0]),
+            };
+            return vec![(head, 0.0)];
+        }
+        let cells = body.iter().copied().chain(self.snake.last_tail).collect::<Vec<_>>();
+        let moving_tail = self.snake.last_tail.is_some();
+        let mut points = Vec::new();
+        for i in 0..cells.len() - 1 {
+            let (to, from) = (cells[i], cells[i + 1]);
+            let (start, end) = (layout.cell_center(from), layout.cell_center(to));
+            if !adjacent(from, to) {
+                points.push((end, i as f32));
+                continue;
             }
-            if x == self.apple.0 && y == self.apple.1 {
-                apples += 1;
+            let tail_piece = moving_tail && i == cells.len() - 2;
+            let (high, low) = match (i, tail_piece) {
+                (_, true) => (1.0, t),
+                (0, _) => (t, 0.0),
+                _ => (1.0, 0.0),
+            };
+            let entered = cells.get(i + 2).copied().filter(|&entered| !tail_piece && adjacent(entered, from));
+            let turning = entered.filter(|&entered| (from.0 - entered.0, from.1 - entered.1) != (to.0 - from.0, to.1 - from.1));
+            let bend = if moving_tail && i == cells.len() - 3 { 1.0 - t } else { 1.0 };
+            let heading = turning.map_or(end - start, |entered| (end - start).lerp(start - layout.cell_center(entered), bend));
+            let samples = if turning.is_some() { Self::CORNER_SAMPLES } else { 1 };
+            for k in 0..samples {
+                let u = high - (high - low) * k as f32 / samples as f32;
+                points.push((mesh::curve_point(start, heading, end, u), i as f32 + k as f32 / samples as f32));
             }
-            len += 1;
-            let collision_index = self.body.iter().take(snakelen - len + apples).position(|&(x_body, y_body)| x_body == x && y_body == y); 
-            if collision_index.is_some() || len >= max_len as usize || (base_x == x && base_y == y) {
-                break;
+        }
+        let last = cells.len() - 1;
+        match self.snake.last_tail {
+            Some(tail) if adjacent(tail, *body.back().unwrap()) => {
+                points.push((layout.cell_center(tail).lerp(layout.cell_center(*body.back().unwrap()), t), last as f32));
             }
+            Some(_) => {}
+            None => points.push((layout.cell_center(cells[last]), last as f32)),
         }
-        len as u32
+        points
+    }
+
+    fn layout(&self, rect: nannou::geom::Rect) -> Layout {
+        Layout::new(rect, self.snake.borders, &self.camera)
     }
 
-    fn path_len(&self, x: i32,  y: i32) -> u32 {
-        let (mut x, mut y) = (x,y);
-        let mut len = 0;
-        while x != self.apple.0 || y != self.apple.1 {
-            match self.path_direction(x, y) {
-                Direction::Up => y += 1,
-                Direction::Down => y -= 1,
-                Direction::Left => x -= 1,
-                Direction::Right => x += 1,
+    fn board_mesh(&self) -> Mesh {
+        let theme = self.theme();
+        let (x0, y0, x1, y1) = self.snake.borders;
+        let mut board = Mesh::default();
+        for i in x0..x1 {
+            for j in y0..y1 {
+                let color = if i == x0 || i == x1 - 1 || j == y0 || j == y1 - 1 || self.snake.walls.contains(&(i, j)) {
+                    theme.border
+                } else if (i + j).rem_euclid(2) == 0 {
+                    theme.board_light
+                } else {
+                    theme.board_dark
+                };
+                board.rect(Vec2::new(i as f32 + 0.5, j as f32 + 0.5), Vec2::splat(0.9), 0.0, color);
             }
-            len += 1;
         }
-        len
+        board
     }
 
-    fn step(&mut self) -> bool {
-        if self.body.len() == 0 || self.body.len() as i32 == (self.borders.2 - self.borders.0 - 2) * (self.borders.3 - self.borders.1 - 2) - 1 {
-            return false;
+
+}
+
+
+
+fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    if !model.keys_down.insert(key) {
+        return;
+    }
+    let actions = model.bindings.actions(key).collect::<Vec<_>>();
+    let screen = model.screen;
+    for action in actions {
+        if model.screen != screen {
+            break;
         }
-        let (x, y) = self.body.front().unwrap();
-        let (x, y) = match self.direction {
-            Direction::Up => (*x, y + 1),
-            Direction::Down => (*x, y - 1),
-            Direction::Left => (x - 1, *y),
-            Direction::Right => (x + 1, *y),
-        };
-        if x == self.apple.0 && y == self.apple.1 {
-            let (x,y) = loop {
-                let random_x = thread_rng().gen_range(self.borders.0 + 1..self.borders.2 - 1);
-                let random_y = thread_rng().gen_range(self.borders.1 + 1..self.borders.3 - 1);
-                if !self.body.contains(&(random_x, random_y)) && !(random_x == x && random_y == y) {
-                    break (random_x, random_y);
-                }
-            };
-            self.apple = (x, y);
-        } else {
-            self.body.pop_back();
+        if action == Action::Fullscreen {
+            let window = app.main_window();
+            window.set_fullscreen(!window.is_fullscreen());
+            continue;
         }
-        if self.body.contains(&(x, y)) || x <= self.borders.0 || x >= self.borders.2 - 1 || y <= self.borders.1 || y >= self.borders.3 - 1 {
-            return false;
+        model.trigger(action);
+        if action.repeats() {
+            model.held.push((action, key, model.bindings.repeat.delay));
         }
-        self.body.push_front((x, y));
-        true
     }
+}
 
-    fn random_dir(dir: Direction) -> Direction {
-        match dir {
-            Direction::Up => {
-                match thread_rng().gen_range(0..3) {
-                    0 => Direction::Left,
-                    1 => Direction::Right,
-                    2 => Direction::Up,
-                    _ => unsafe { unreachable_unchecked() }
-                }
-            }
-            Direction::Down => {
-                match thread_rng().gen_range(0..3) {
-                    0 => Direction::Left,
-                    1 => Direction::Right,
-                    2 => Direction::Up,
-                    _ => unsafe { unreachable_unchecked() }
-                }
-            }
-            Direction::Left => {
-                match thread_rng().gen_range(0..3) {
-                    0 => Direction::Up,
-                    1 => Direction::Down,
-                    2 => Direction::Right,
-                    _ => unsafe { unreachable_unchecked() }
-                }
-            }
-            Direction::Right => {
-                match thread_rng().gen_range(0..3) {
-                    0 => Direction::Up,
-                    1 => Direction::Down,
-                    2 => Direction::Left,
-                    _ => unsafe { unreachable_unchecked() }
-                }
+fn mouse_pressed(app: &App, model: &mut Model, button: MouseButton) {
+    let cell = model.layout(app.window_rect()).cell_at(app.mouse.position());
+    match model.screen {
+        Screen::LevelSelect | Screen::GameOver | Screen::Replay => return,
+        Screen::Editor => {
+            match button {
+                MouseButton::Left => model.snake.edit_body(cell),
+                MouseButton::Right => model.snake.place_apple(cell),
+                MouseButton::Middle => model.snake.toggle_wall(cell),
+                _ => {}
             }
+            return;
+        }
+        Screen::Game => {}
+    }
+    if button == MouseButton::Middle {
+        model.drag = Some(app.mouse.position());
+        return;
+    }
+    if !model.debug {
+        if button == MouseButton::Left {
+            model.running = !model.running;
         }
+        return;
+    }
+    match button {
+        MouseButton::Left => model.snake.place_apple(cell),
+        MouseButton::Right => model.snake.toggle_wall(cell),
+        _ => {}
     }
+}
 
-    fn bot_move(model: &mut Model) -> Direction {
-        let &(x, y) = model.snake.body.front().unwrap();
-        let mut dir = model.snake.path_direction(x, y);
-        let mut path_len = model.snake.path_len(x, y);
-        let snake_len = model.snake.body.len() as u32;
-        
-        if y < model.snake.borders.3 - 2
-        && x < model.snake.borders.2 - 2
-        && model.snake.path_len(x, y + 1) + 1 < path_len
-        && !model.snake.body.contains(&(x, y + 1))
-        && snake_len <= model.snake.free_path_len(x, y + 1, snake_len) {
-            path_len = model.snake.path_len(x, y + 1) + 1;
-            dir = Direction::Up;
-        }
-        if y > model.snake.borders.1 + 1
-        && x < model.snake.borders.2 - 2
-        && model.snake.path_len(x, y - 1) + 1 < path_len
-        && !model.snake.body.contains(&(x, y - 1))
-        && snake_len <= model.snake.free_path_len(x, y - 1, snake_len) {
-            path_len = model.snake.path_len(x, y - 1) + 1;
-            dir = Direction::Down;
-        }
-
-        if x > model.snake.borders.0 + 1
-        && y < model.snake.borders.3 - 2
-        && model.snake.path_len(x - 1, y) + 1 < path_len
-        && !model.snake.body.contains(&(x - 1, y))
-        && snake_len <= model.snake.free_path_len(x - 1, y, snake_len) {
-            path_len = model.snake.path_len(x - 1, y) + 1;
-            dir = Direction::Left;
-        }   
-
-        if x < model.snake.borders.2 - 2
-        && y < model.snake.borders.3 - 2
-        && model.snake.path_len(x + 1, y) + 1 < path_len
-        && !model.snake.body.contains(&(x + 1, y))
-        && snake_len <= model.snake.free_path_len(x + 1, y, snake_len) {
-            dir = Direction::Right;
-        }
-        dir 
+fn mouse_moved(app: &App, model: &mut Model, position: Vec2) {
+    if let Some(last) = model.drag.replace(position) {
+        let cell = model.layout(app.window_rect()).cell;
+        model.camera.pan_by((last - position) / cell, model.snake.borders);
     }
 }
 
+fn mouse_released(_app: &App, model: &mut Model, button: MouseButton) {
+    if button == MouseButton::Middle {
+        model.drag = None;
+    }
+}
+
+fn mouse_wheel(_app: &App, model: &mut Model, delta: MouseScrollDelta, _phase: TouchPhase) {
+    let lines = match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+    };
+    model.speed *= model.settings.speed.step.powf(lines);
+}
+
+fn key_released(_app: &App, model: &mut Model, key: Key) {
+    model.keys_down.remove(&key);
+    model.held.retain(|&(_, held_key, _)| held_key != key);
+}
@@ -0,0 +1,66 @@
+use nannou::{color::Rgba, geom::Rect, glam::{Vec2, Vec3}, Draw};
+
+use crate::theme::Color;
+
+#[derive(Default)]
+pub struct Mesh {
+    vertices: Vec<(Vec3, Rgba)>,
+}
+
+impl Mesh {
+    fn triangle(&mut self, points: [Vec2; 3], z: f32, color: Rgba) {
+        self.vertices.extend(points.map(|point| (point.extend(z), color)));
+    }
+
+    fn quad(&mut self, corners: [Vec2; 4], z: f32, color: Color) {
+        let color = Rgba::from(color);
+        self.triangle([corners[0], corners[1], corners[2]], z, color);
+        self.triangle([corners[0], corners[2], corners[3]], z, color);
+    }
+
+    pub fn rect(&mut self, center: Vec2, size: Vec2, z: f32, color: Color) {
+        let half = size / 2.0;
+        self.quad([
+            center + Vec2::new(-half.x, -half.y),
+            center + Vec2::new(half.x, -half.y),
+            center + Vec2::new(half.x, half.y),
+            center + Vec2::new(-half.x, half.y),
+        ], z, color);
+    }
+
+    pub fn line(&mut self, start: Vec2, end: Vec2, width: f32, z: f32, color: Color) {
+        let Some(direction) = (end - start).try_normalize() else {
+            return;
+        };
+        let normal = direction.perp() * width / 2.0;
+        self.quad([start - normal, end - normal, end + normal, start + normal], z, color);
+    }
+
+    pub fn circle(&mut self, center: Vec2, radius: f32, z: f32, color: Color) {
+        let color = Rgba::from(color);
+        let resolution = (radius as usize).clamp(6, 32);
+        let point = |i: usize| {
+            let angle = std::f32::consts::TAU * i as f32 / resolution as f32;
+            center + Vec2::new(angle.cos(), angle.sin()) * radius
+        };
+        for i in 0..resolution {
+            self.triangle([center, point(i), point(i + 1)], z, color);
+        }
+    }
+
+    pub fn draw(&self, draw: &Draw) {
+        if !self.vertices.is_empty() {
+            draw.mesh().points_colored(self.vertices.iter().copied());
+        }
+    }
+}
+
+pub fn visible(rect: Rect, center: Vec2, radius: f32) -> bool {
+    center.x + radius >= rect.left() && center.x - radius <= rect.right() && center.y + radius >= rect.bottom() && center.y - radius <= rect.top()
+}
+
+pub fn curve_point(start: Vec2, heading: Vec2, end: Vec2, t: f32) -> Vec2 {
+    let (a, b) = (start + heading * 0.5, start.lerp(end, 0.5));
+    let u = 1.0 - t;
+    start * u * u * u + a * 3.0 * u * u * t + b * 3.0 * u * t * t + end * t * t * t
+}
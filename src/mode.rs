@@ -0,0 +1,168 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Endless,
+    TimeAttack,
+    Sprint,
+    Survival,
+}
+
+impl Mode {
+    pub const TIME_ATTACK_SECONDS: f32 = 60.0;
+    pub const SPRINT_LENGTH: u32 = 30;
+    pub const SURVIVAL_RAMP: f32 = 1.1;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Endless => "Endless",
+            Mode::TimeAttack => "Time attack",
+            Mode::Sprint => "Sprint",
+            Mode::Survival => "Survival",
+        }
+    }
+
+    pub fn next(self) -> Mode {
+        match self {
+            Mode::Endless => Mode::TimeAttack,
+            Mode::TimeAttack => Mode::Sprint,
+            Mode::Sprint => Mode::Survival,
+            Mode::Survival => Mode::Endless,
+        }
+    }
+
+    pub fn speed_multiplier(self, apples: u32) -> f32 {
+        match self {
+            Mode::Survival => Mode::SURVIVAL_RAMP.powi(apples as i32),
+            _ => 1.0,
+        }
+    }
+
+    pub fn lower_is_better(self) -> bool {
+        self == Mode::Sprint
+    }
+
+    pub fn format_score(self, score: f32) -> String {
+        match self {
+            Mode::Endless | Mode::Survival => format!("length {}", score),
+            Mode::TimeAttack => format!("{} apples", score),
+            Mode::Sprint => format!("{:.2}s", score),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Outcome {
+    Died,
+    BoardFull,
+    TimeUp,
+    SprintDone,
+    LevelComplete,
+}
+
+impl Outcome {
+    pub fn title(self) -> &'static str {
+        match self {
+            Outcome::Died => "Game over",
+            Outcome::BoardFull => "Board cleared!",
+            Outcome::TimeUp => "Time's up",
+            Outcome::SprintDone => "Sprint finished!",
+            Outcome::LevelComplete => "Level complete!",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ScoreKey {
+    pub width: u32,
+    pub height: u32,
+    pub mode: Mode,
+    pub bot: bool,
+}
+
+impl ScoreKey {
+    pub fn describe(&self) -> String {
+        format!("{} {}x{} {}", self.mode.name(), self.width, self.height, if self.bot { "bot" } else { "player" })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Score {
+    pub score: f32,
+    pub date: String,
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Table {
+    #[serde(flatten)]
+    key: ScoreKey,
+    scores: Vec<Score>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Highscores {
+    pub player: Option<String>,
+    #[serde(default)]
+    tables: Vec<Table>,
+}
+
+impl Highscores {
+    const TABLE_SIZE: usize = 10;
+    const FILE: &'static str = "highscores.toml";
+
+    pub fn load() -> Self {
+        crate::storage::load(Highscores::FILE)
+    }
+
+    pub fn table(&self, key: &ScoreKey) -> &[Score] {
+        self.tables
+            .iter()
+            .find(|table| table.key == *key)
+            .map_or(&[], |table| table.scores.as_slice())
+    }
+
+    pub fn best(&self, key: &ScoreKey) -> Option<f32> {
+        self.table(key).first().map(|score| score.score)
+    }
+
+    pub fn submit(&mut self, key: ScoreKey, score: f32) -> Option<usize> {
+        let index = match self.tables.iter().position(|table| table.key == key) {
+            Some(index) => index,
+            None => {
+                self.tables.push(Table { key, scores: Vec::new() });
+                self.tables.len() - 1
+            }
+        };
+        let table = &mut self.tables[index].scores;
+        let rank = table
+            .iter()
+            .position(|other| if key.mode.lower_is_better() { score < other.score } else { score > other.score })
+            .unwrap_or(table.len());
+        if rank >= Highscores::TABLE_SIZE {
+            return None;
+        }
+        let name = if key.bot { None } else { self.player.clone() };
+        table.insert(rank, Score { score, date: today(), name });
+        table.truncate(Highscores::TABLE_SIZE);
+        crate::storage::save(Highscores::FILE, self);
+        Some(rank)
+    }
+}
+
+fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs() / 86_400) as i64;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
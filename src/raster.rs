@@ -0,0 +1,150 @@
+use image::{Rgba, RgbaImage};
+
+use crate::{snake::{AppleRule, Direction, Item, Snake}, theme::{Color, Theme}};
+
+struct Canvas {
+    image: RgbaImage,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: Color) -> Self {
+        let mut canvas = Canvas { image: RgbaImage::new(width, height) };
+        canvas.fill(0.0, 0.0, width as f32, height as f32, |_, _| true, background.with_alpha(1.0));
+        canvas
+    }
+
+    fn into_image(self) -> RgbaImage {
+        self.image
+    }
+
+    fn fill(&mut self, left: f32, top: f32, right: f32, bottom: f32, inside: impl Fn(f32, f32) -> bool, color: Color) {
+        let x0 = left.floor().max(0.0) as u32;
+        let y0 = top.floor().max(0.0) as u32;
+        let x1 = (right.ceil().max(0.0) as u32).min(self.image.width());
+        let y1 = (bottom.ceil().max(0.0) as u32).min(self.image.height());
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if inside(x as f32 + 0.5, y as f32 + 0.5) {
+                    let pixel = self.image.get_pixel_mut(x, y);
+                    *pixel = blend(*pixel, color);
+                }
+            }
+        }
+    }
+
+    fn rect(&mut self, (x, y): (f32, f32), (w, h): (f32, f32), color: Color) {
+        self.fill(x - w / 2.0, y - h / 2.0, x + w / 2.0, y + h / 2.0, |_, _| true, color);
+    }
+
+    fn diamond(&mut self, (x, y): (f32, f32), radius: f32, color: Color) {
+        self.fill(x - radius, y - radius, x + radius, y + radius, |px, py| (px - x).abs() + (py - y).abs() <= radius, color);
+    }
+
+    fn circle(&mut self, (x, y): (f32, f32), radius: f32, color: Color) {
+        self.fill(x - radius, y - radius, x + radius, y + radius, |px, py| (px - x).powi(2) + (py - y).powi(2) <= radius * radius, color);
+    }
+
+    fn ring(&mut self, (x, y): (f32, f32), radius: f32, width: f32, color: Color) {
+        let outer = radius + width / 2.0;
+        let inner = radius - width / 2.0;
+        self.fill(x - outer, y - outer, x + outer, y + outer, |px, py| {
+            let distance = (px - x).powi(2) + (py - y).powi(2);
+            distance <= outer * outer && distance >= inner * inner
+        }, color);
+    }
+
+    fn line(&mut self, start: (f32, f32), end: (f32, f32), width: f32, color: Color) {
+        let radius = width / 2.0;
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let length = dx * dx + dy * dy;
+        self.fill(start.0.min(end.0) - radius, start.1.min(end.1) - radius, start.0.max(end.0) + radius, start.1.max(end.1) + radius, |px, py| {
+            let t = if length == 0.0 { 0.0 } else { (((px - start.0) * dx + (py - start.1) * dy) / length).clamp(0.0, 1.0) };
+            (px - start.0 - t * dx).powi(2) + (py - start.1 - t * dy).powi(2) <= radius * radius
+        }, color);
+    }
+}
+
+fn blend(pixel: Rgba<u8>, color: Color) -> Rgba<u8> {
+    let channel = |old: u8, new: f32| (old as f32 + (new.clamp(0.0, 1.0) * 255.0 - old as f32) * color.a.clamp(0.0, 1.0)).round() as u8;
+    Rgba([channel(pixel[0], color.r), channel(pixel[1], color.g), channel(pixel[2], color.b), 255])
+}
+
+pub fn render(snake: &Snake, theme: &Theme, cell: u32, show_cycle: bool) -> RgbaImage {
+    let (x0, y0, x1, y1) = snake.borders;
+    let sqare_size = cell as f32;
+    let mut canvas = Canvas::new((x1 - x0) as u32 * cell, (y1 - y0) as u32 * cell, theme.background);
+    let to_pixels = |(x, y): (i32, i32)| ((x - x0) as f32 * sqare_size + sqare_size / 2.0, (y1 - 1 - y) as f32 * sqare_size + sqare_size / 2.0);
+
+    for i in x0..x1 {
+        for j in y0..y1 {
+            let color = if i == x0 || i == x1 - 1 || j == y0 || j == y1 - 1 || snake.walls.contains(&(i, j)) {
+                theme.border
+            } else if (i + j).rem_euclid(2) == 0 {
+                theme.board_light
+            } else {
+                theme.board_dark
+            };
+            canvas.rect(to_pixels((i, j)), (sqare_size * 0.9, sqare_size * 0.9), color);
+        }
+    }
+
+    if show_cycle {
+        for i in 0..snake.direction_path.len() {
+            let (x, y) = snake.path_cell(i);
+            let next = match snake.direction_path[i] {
+                Direction::Up => (x, y + 1),
+                Direction::Down => (x, y - 1),
+                Direction::Left => (x - 1, y),
+                Direction::Right => (x + 1, y),
+            };
+            canvas.line(to_pixels((x, y)), to_pixels(next), (sqare_size * 0.08).max(1.0), theme.path);
+        }
+    }
+
+    for (i, &(a, b)) in snake.portals.iter().enumerate() {
+        let hue = nannou::color::Hsv::new(360.0 * (i as f32 / snake.portals.len() as f32 + 0.5), 0.8, 1.0);
+        let rgb = nannou::color::Rgb::from(hue);
+        let color = Color::rgb(rgb.red, rgb.green, rgb.blue);
+        canvas.line(to_pixels(a), to_pixels(b), (sqare_size * 0.05).max(1.0), color.with_alpha(0.2));
+        for cell in [a, b] {
+            canvas.ring(to_pixels(cell), sqare_size * 0.375, sqare_size * 0.12, color);
+        }
+    }
+
+    let apple_alpha = if snake.rules.apple == AppleRule::Expiring {
+        let remaining = snake.apple_lifetime().saturating_sub(snake.apple_age);
+        if remaining < 10 && remaining.is_multiple_of(2) { 0.3 } else { 1.0 }
+    } else {
+        1.0
+    };
+    canvas.circle(to_pixels(snake.apple), sqare_size * 0.35, theme.apple.with_alpha(apple_alpha));
+
+    if let Some((item, position, lifetime)) = snake.item {
+        let center = to_pixels(position);
+        let alpha = if lifetime < 20 && lifetime % 4 < 2 { 0.3 } else { 1.0 };
+        match item {
+            Item::Golden => canvas.circle(center, sqare_size * 0.4, theme.golden.with_alpha(alpha)),
+            Item::SlowMotion => {
+                canvas.circle(center, sqare_size * 0.35, theme.slow_motion.with_alpha(alpha));
+                canvas.ring(center, sqare_size * 0.35, sqare_size * 0.1, theme.slow_motion.lerp(theme.text, 0.7).with_alpha(alpha));
+            }
+            Item::Shrink => canvas.diamond(center, sqare_size * 0.35, theme.shrink.with_alpha(alpha)),
+            Item::Ghost => canvas.circle(center, sqare_size * 0.35, theme.ghost.with_alpha(alpha)),
+        }
+    }
+
+    let points = snake.body.iter().map(|&cell| to_pixels(cell)).collect::<Vec<_>>();
+    let len = points.len();
+    let base = 0.55f32.powf(1.0 / len as f32).max(0.94);
+    let size_mult = (len as f32 / 200.0).clamp(0.6, 0.87);
+    let alpha = if snake.ghost > 0 { 0.5 } else { 1.0 };
+    let tail_size = base.powi(len as i32 - 1) * sqare_size;
+    canvas.circle(points[len - 1], tail_size * size_mult / 2.0, theme.snake_color(1.0 / len as f32).with_alpha(alpha));
+    for i in (0..len - 1).rev() {
+        let width = base.powi(i as i32) * sqare_size * size_mult;
+        canvas.line(points[i], points[i + 1], width * base, theme.snake_color((len - i - 1) as f32 / len as f32).with_alpha(alpha));
+        canvas.circle(points[i], width / 2.0, theme.snake_color((len - i) as f32 / len as f32).with_alpha(alpha));
+    }
+
+    canvas.into_image()
+}
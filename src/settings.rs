@@ -0,0 +1,197 @@
+use std::{fs, path::PathBuf, time::SystemTime};
+
+use serde::Deserialize;
+
+use crate::{input::BindingsConfig, mode::Mode, snake::{AppleRule, Cycle, Rules}, theme::Theme};
+
+#[derive(PartialEq, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RuleSettings {
+    pub width: u32,
+    pub height: u32,
+    pub mode: Mode,
+    pub power_ups: bool,
+    pub apple: AppleRule,
+    pub portals: u32,
+}
+
+impl Default for RuleSettings {
+    fn default() -> Self {
+        RuleSettings {
+            width: 10,
+            height: 10,
+            mode: Mode::Endless,
+            power_ups: false,
+            apple: AppleRule::Static,
+            portals: 0,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SpeedSettings {
+    pub initial: f32,
+    pub step: f32,
+    pub start_delay: f32,
+}
+
+impl Default for SpeedSettings {
+    fn default() -> Self {
+        SpeedSettings {
+            initial: 0.1,
+            step: 1.2,
+            start_delay: 1.0,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ColorSettings {
+    pub theme: Option<String>,
+    #[serde(flatten)]
+    pub overrides: toml::Table,
+}
+
+#[derive(PartialEq, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct BotSettings {
+    pub enabled: bool,
+    pub cycle: Cycle,
+    pub shortcuts: bool,
+    pub restart_delay: f32,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        BotSettings {
+            enabled: true,
+            cycle: Cycle::Random,
+            shortcuts: true,
+            restart_delay: 3.0,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            width: 800,
+            height: 800,
+            fullscreen: false,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub rules: RuleSettings,
+    pub speed: SpeedSettings,
+    pub colors: ColorSettings,
+    pub keys: BindingsConfig,
+    pub bot: BotSettings,
+    pub window: WindowSettings,
+}
+
+impl Settings {
+    pub const FILE: &'static str = "settings.toml";
+    const CHECK_INTERVAL: f32 = 0.5;
+
+    pub fn load() -> Self {
+        crate::storage::load_config(Settings::FILE)
+    }
+
+    pub fn location() -> String {
+        crate::storage::config_file(Settings::FILE).map_or(Settings::FILE.to_string(), |path| path.display().to_string())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rules.width < 4 || self.rules.height < 4 {
+            return Err("The board needs at least 4x4 cells".to_string());
+        }
+        if !self.rules.width.is_multiple_of(2) && !self.rules.height.is_multiple_of(2) {
+            return Err("The board needs an even width or height to fit a Hamiltonian cycle".to_string());
+        }
+        if self.speed.initial <= 0.0 || self.speed.step <= 1.0 {
+            return Err("The speed must be positive and the speed step larger than 1".to_string());
+        }
+        let themes = self.themes()?;
+        if let Some(name) = &self.colors.theme {
+            if !themes.iter().any(|theme| theme.name.eq_ignore_ascii_case(name)) {
+                return Err(format!("Unknown theme {}", name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rules(&self) -> Rules {
+        Rules {
+            power_ups: self.rules.power_ups,
+            apple: self.rules.apple,
+            cycle: self.bot.cycle,
+            shortcuts: self.bot.shortcuts,
+        }
+    }
+
+    pub fn themes(&self) -> Result<Vec<Theme>, String> {
+        Theme::load_all().iter().map(|theme| theme.with_colors(&self.colors.overrides)).collect()
+    }
+
+    pub fn theme_index(&self, themes: &[Theme]) -> Option<usize> {
+        let name = self.colors.theme.as_ref()?;
+        themes.iter().position(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+}
+
+pub struct Watcher {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    timer: f32,
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        let path = crate::storage::config_file(Settings::FILE);
+        let modified = Watcher::modified(&path);
+        Watcher { path, modified, timer: 0.0 }
+    }
+
+    fn modified(path: &Option<PathBuf>) -> Option<SystemTime> {
+        fs::metadata(path.as_ref()?).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    pub fn poll(&mut self, dt: f32, overrides: impl FnOnce(&mut Settings)) -> Option<Settings> {
+        self.timer += dt;
+        if self.timer < Settings::CHECK_INTERVAL {
+            return None;
+        }
+        self.timer = 0.0;
+        let modified = Watcher::modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let mut settings = match modified {
+            Some(_) => crate::storage::try_load_config::<Settings>(Settings::FILE)?,
+            None => Settings::default(),
+        };
+        overrides(&mut settings);
+        match settings.validate() {
+            Ok(()) => Some(settings),
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", Settings::FILE, e);
+                None
+            }
+        }
+    }
+}
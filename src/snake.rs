@@ -0,0 +1,1049 @@
+use std::{collections::{HashSet, VecDeque}, hint::unreachable_unchecked, sync::Arc};
+
+use nannou::rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Item {
+    Golden,
+    SlowMotion,
+    Shrink,
+    Ghost,
+}
+
+impl Item {
+    const SPAWN_CHANCES: [(Item, f32); 4] = [
+        (Item::Golden, 0.10),
+        (Item::SlowMotion, 0.06),
+        (Item::Shrink, 0.06),
+        (Item::Ghost, 0.04),
+    ];
+
+    pub const GOLDEN_GROWTH: u32 = 3;
+    pub const SHRINK_SEGMENTS: usize = 3;
+    pub const SLOW_MOTION_STEPS: u32 = 100;
+    pub const GHOST_STEPS: u32 = 60;
+
+    fn random(rng: &mut impl Rng) -> Option<Item> {
+        let mut roll = rng.gen::<f32>();
+        for (item, chance) in Item::SPAWN_CHANCES {
+            if roll < chance {
+                return Some(item);
+            }
+            roll -= chance;
+        }
+        None
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppleRule {
+    #[default]
+    Static,
+    Expiring,
+    Drifting,
+}
+
+impl AppleRule {
+    pub const DRIFT_INTERVAL: u32 = 4;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AppleRule::Static => "static",
+            AppleRule::Expiring => "expiring",
+            AppleRule::Drifting => "drifting",
+        }
+    }
+
+    pub fn next(self) -> AppleRule {
+        match self {
+            AppleRule::Static => AppleRule::Expiring,
+            AppleRule::Expiring => AppleRule::Drifting,
+            AppleRule::Drifting => AppleRule::Static,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Cycle {
+    #[default]
+    Random,
+    Zigzag,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Rules {
+    pub power_ups: bool,
+    pub apple: AppleRule,
+    pub cycle: Cycle,
+    pub shortcuts: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            power_ups: false,
+            apple: AppleRule::Static,
+            cycle: Cycle::Random,
+            shortcuts: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Position {
+    pub borders: (i32, i32, i32, i32),
+    pub body: Vec<(i32, i32)>,
+    pub direction: Direction,
+    pub apple: (i32, i32),
+    pub walls: Vec<(i32, i32)>,
+    pub portals: Vec<((i32, i32), (i32, i32))>,
+}
+
+impl Position {
+    pub fn check(&self) -> Result<(), String> {
+        let (x0, y0, x1, y1) = self.borders;
+        let inside = |&(x, y): &(i32, i32)| x > x0 && x < x1 - 1 && y > y0 && y < y1 - 1;
+        if x1 - x0 < 6 || y1 - y0 < 6 {
+            return Err("the board is smaller than 4x4".to_string());
+        }
+        if self.body.is_empty() || !self.body.iter().all(inside) {
+            return Err("the snake is outside of the board".to_string());
+        }
+        if !inside(&self.apple) || !self.walls.iter().all(inside) || !self.portals.iter().all(|(a, b)| inside(a) && inside(b)) {
+            return Err("the apple, a wall or a portal is outside of the board".to_string());
+        }
+        let mut body = HashSet::new();
+        if !self.body.iter().all(|&cell| body.insert(cell)) {
+            return Err("the snake overlaps itself".to_string());
+        }
+        if self.body.iter().any(|cell| self.walls.contains(cell)) {
+            return Err("the snake overlaps a wall".to_string());
+        }
+        let mut taken = HashSet::from([self.apple]);
+        if !self.walls.iter().chain(self.portals.iter().flat_map(|(a, b)| [a, b])).all(|&cell| taken.insert(cell)) {
+            return Err("walls, portals and the apple overlap".to_string());
+        }
+        let partner = |cell: (i32, i32)| self.portals.iter().find_map(|&(a, b)| if a == cell { Some(b) } else if b == cell { Some(a) } else { None });
+        let adjacent = |(x, y): (i32, i32), cell: (i32, i32)| {
+            [(x, y + 1), (x, y - 1), (x - 1, y), (x + 1, y)].into_iter().any(|next| next == cell || partner(next) == Some(cell))
+        };
+        if !self.body.windows(2).all(|pair| adjacent(pair[0], pair[1]) || adjacent(pair[1], pair[0])) {
+            return Err("the snake is not connected".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct State {
+    pub borders: (i32, i32, i32, i32),
+    pub body: Vec<(i32, i32)>,
+    pub direction: Direction,
+    pub apple: (i32, i32),
+    pub apple_age: u32,
+    pub rules: Rules,
+    pub walls: Vec<(i32, i32)>,
+    pub portals: Vec<((i32, i32), (i32, i32))>,
+    pub item: Option<(Item, (i32, i32), u32)>,
+    pub growth: u32,
+    pub last_tail: Option<(i32, i32)>,
+    pub apples: u32,
+    pub steps: u32,
+    pub slow_motion: u32,
+    pub ghost: u32,
+    // TOML integers are signed, the bits are reinterpreted as the u64 seed
+    pub seed: i64,
+    pub direction_path: Vec<Direction>,
+}
+
+#[derive(Clone)]
+pub struct Undo {
+    head: Option<(i32, i32)>,
+    len: usize,
+    tail: Vec<(i32, i32)>,
+    direction: Direction,
+    pub apple: (i32, i32),
+    apple_age: u32,
+    item: Option<(Item, (i32, i32), u32)>,
+    growth: u32,
+    last_tail: Option<(i32, i32)>,
+    pub apples: u32,
+    steps: u32,
+    slow_motion: u32,
+    ghost: u32,
+    rng: Pcg64Mcg,
+}
+
+#[derive(Clone)]
+pub struct Snake {
+    pub body: VecDeque<(i32, i32)>,
+    pub direction: Direction,
+    pub borders: (i32, i32, i32, i32),
+    pub apple: (i32, i32),
+    pub apple_age: u32,
+    pub direction_path: Arc<Vec<Direction>>,
+    pub rules: Rules,
+    pub walls: HashSet<(i32, i32)>,
+    pub portals: Vec<((i32, i32), (i32, i32))>,
+    pub item: Option<(Item, (i32, i32), u32)>,
+    pub growth: u32,
+    pub last_tail: Option<(i32, i32)>,
+    pub apples: u32,
+    pub steps: u32,
+    pub slow_motion: u32,
+    pub ghost: u32,
+    rng: Pcg64Mcg,
+}
+
+impl Snake {
+    const CANDIDATE_BATCH: usize = 256;
+    const RANDOM_TRIES: usize = 64;
+    const STARVATION_FACTOR: u32 = 2;
+    const DESPERATION_FACTOR: u32 = 50;
+
+    pub fn board_borders((width, height): (u32, u32)) -> (i32, i32, i32, i32) {
+        let (width, height) = (width as i32 + 2, height as i32 + 2);
+        (-width / 2, -height / 2, width - width / 2, height - height / 2)
+    }
+
+    pub fn with_seed(borders: (i32, i32, i32, i32), rules: Rules, seed: u64) -> Self {
+        let mut rng = Pcg64Mcg::seed_from_u64(seed);
+        let mut body = VecDeque::new();
+        body.push_back((0, 0));
+        Snake {
+            body,
+            direction: Direction::Up,
+            borders,
+            apple: (1, 1),
+            apple_age: 0,
+            direction_path: Arc::new(Snake::init_path_direction((borders.2 - borders.0 - 2) as u32, (borders.3 - borders.1 - 2) as u32, rules.cycle, &mut rng)),
+            rules,
+            walls: HashSet::new(),
+            portals: Vec::new(),
+            item: None,
+            growth: 0,
+            last_tail: None,
+            apples: 0,
+            steps: 0,
+            slow_motion: 0,
+            ghost: 0,
+            rng,
+        }
+    }
+
+
+    fn zigzag(width: u32, height: u32) -> Vec<Direction> {
+        (0..height).flat_map(|y |
+            (0..width).map(move |x| {
+                if y == height - 1 && x > 0 {
+                    Direction::Left
+                } else if x % 2 == 0 {
+                    if y == 0 {
+                        Direction::Right
+                    } else {
+                        Direction::Down
+                    }
+                } else if y == height - 2 && x < width - 1 {
+                    Direction::Right
+                } else {
+                    Direction::Up
+                }
+            })
+        ).collect()
+    }
+
+    fn init_path_direction(width: u32, height: u32, cycle: Cycle, rng: &mut Pcg64Mcg) -> Vec<Direction> {
+        let mut path = if width.is_multiple_of(2) {
+            Snake::zigzag(width, height)
+        } else if height.is_multiple_of(2) {
+            let transposed = Snake::zigzag(height, width);
+            (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| match transposed[(y + x * height) as usize] {
+                    Direction::Up => Direction::Right,
+                    Direction::Down => Direction::Left,
+                    Direction::Left => Direction::Down,
+                    Direction::Right => Direction::Up,
+                })
+                .collect()
+        } else {
+            return Vec::new();
+        };
+
+        let num_iterations = match cycle {
+            Cycle::Random => (width * height) as u64,
+            Cycle::Zigzag => 0,
+        };
+
+        let progress = indicatif::ProgressBar::new(num_iterations);
+        progress.inc(0);
+        let w = width as i32;
+        let offsets = [-1, -w + 1, -w, -w - 1, 1, w - 1, w + 1, w];
+
+        for _ in 0..num_iterations {
+            progress.inc(1);
+            let tmp_path = loop {
+                let candidates = (0..Snake::CANDIDATE_BATCH)
+                    .map(|_| {
+                        let random_index_1 = rng.gen_range(0..path.len());
+                        let random_index_2 = (random_index_1 as i32 + offsets[rng.gen_range(0..8)]).clamp(0, path.len() as i32 - 1) as usize;
+                        let random_index_3 = rng.gen_range(0..path.len());
+                        let random_index_4 = (random_index_3 as i32 + offsets[rng.gen_range(0..8)]).clamp(0, path.len() as i32 - 1) as usize;
+
+                        let random_dir_1 = Snake::random_dir(path[random_index_1], rng);
+                        let random_dir_2 = Snake::random_dir(path[random_index_2], rng);
+                        let random_dir_3 = Snake::random_dir(path[random_index_3], rng);
+                        let random_dir_4 = Snake::random_dir(path[random_index_4], rng);
+                        (random_index_1, random_index_2, random_index_3, random_index_4, random_dir_1, random_dir_2, random_dir_3, random_dir_4)
+                    })
+                    .collect::<Vec<_>>();
+                let found = candidates
+                    .into_par_iter()
+                    .find_first(|(random_index1, random_index2, random_index3, random_index4, random_dir1, random_dir2, random_dir3, random_dir4)| {
+                        let mut x = 0;
+                        let mut y = 0;
+                        let mut path_len = 0;
+                        let mut seen: Vec<bool> = vec![false; path.len()];
+                        let mut new_path = path.clone();
+                        new_path[*random_index1] = *random_dir1;
+                        new_path[*random_index2] = *random_dir2;
+                        new_path[*random_index3] = *random_dir3;
+                        new_path[*random_index4] = *random_dir4;
+                        loop {
+                            if seen[x as usize + y as usize * width as usize] {
+                                break;
+                            }
+                            seen[x as usize + y as usize * width as usize] = true;
+                            match new_path[x as usize + y as usize * width as usize] {
+                                Direction::Up => y += 1,
+                                Direction::Down => y -= 1,
+                                Direction::Left => x -= 1,
+                                Direction::Right => x += 1,
+                            }
+                            path_len += 1;
+                            if (x == 0 && y == 0) || x < 0 || x >= width as i32 || y < 0 || y >= height as i32 {
+                                break;
+                            }
+                        }
+
+                        path_len == new_path.len() && x == 0 && y == 0
+                    });
+                if let Some(found) = found {
+                    break found;
+                }
+            };
+
+            let (random_index1, random_index2, random_index3, random_index4, random_dir1, random_dir2, random_dir3, random_dir4) = tmp_path;
+            path[random_index1] = random_dir1;
+            path[random_index2] = random_dir2;
+            path[random_index3] = random_dir3;
+            path[random_index4] = random_dir4;
+        }
+
+
+        path
+    }
+
+    pub fn path_direction(&self, x: i32, y: i32) -> Direction {
+        self.direction_path[(y - self.borders.1 - 1) as usize * (self.borders.2 - self.borders.0 - 2) as usize + (x - self.borders.0 - 1) as usize]
+    }
+
+    pub fn path_cell(&self, index: usize) -> (i32, i32) {
+        let width = (self.borders.2 - self.borders.0 - 2) as usize;
+        (self.borders.0 + 1 + (index % width) as i32, self.borders.1 + 1 + (index / width) as i32)
+    }
+
+    pub fn cycle_order(&self) -> Vec<usize> {
+        let width = (self.borders.2 - self.borders.0 - 2) as usize;
+        let mut order = vec![0; self.direction_path.len()];
+        let mut index = 0;
+        for position in 0..order.len() {
+            order[index] = position;
+            let (x, y) = self.path_cell(index);
+            let (x, y) = match self.direction_path[index] {
+                Direction::Up => (x, y + 1),
+                Direction::Down => (x, y - 1),
+                Direction::Left => (x - 1, y),
+                Direction::Right => (x + 1, y),
+            };
+            index = (x - self.borders.0 - 1) as usize + (y - self.borders.1 - 1) as usize * width;
+        }
+        order
+    }
+
+    pub fn path_to_apple(&self) -> Vec<(i32, i32)> {
+        let (mut x, mut y) = *self.body.front().unwrap();
+        let mut cells = vec![(x, y)];
+        if self.direction_path.is_empty() {
+            return cells;
+        }
+        while x != self.apple.0 || y != self.apple.1 {
+            match self.path_direction(x, y) {
+                Direction::Up => y += 1,
+                Direction::Down => y -= 1,
+                Direction::Left => x -= 1,
+                Direction::Right => x += 1,
+            }
+            cells.push((x, y));
+        }
+        cells
+    }
+
+    fn free_path_len(&self, base_x: i32, base_y: i32, max_len: u32) -> u32 {
+        let (mut x, mut y) = (base_x, base_y);
+        let mut len = 0;
+        let snakelen = self.body.len();
+        // A drifting apple may move onto the path ahead, so assume it gets eaten along the way
+        let drifting = self.rules.apple == AppleRule::Drifting;
+        let mut apples = drifting as usize;
+        loop {
+            match self.path_direction(x, y) {
+                Direction::Up => y += 1,
+                Direction::Down => y -= 1,
+                Direction::Left => x -= 1,
+                Direction::Right => x += 1,
+            }
+            if !drifting && x == self.apple.0 && y == self.apple.1 {
+                apples += 1;
+            }
+            len += 1;
+            let collision_index = self.body.iter().take(snakelen - len + apples + self.growth as usize).position(|&(x_body, y_body)| x_body == x && y_body == y);
+            if collision_index.is_some() || len >= max_len as usize || (base_x == x && base_y == y) {
+                break;
+            }
+        }
+        len as u32
+    }
+
+    fn path_len(&self, x: i32,  y: i32) -> u32 {
+        let (mut x, mut y) = (x,y);
+        let mut len = 0;
+        while x != self.apple.0 || y != self.apple.1 {
+            match self.path_direction(x, y) {
+                Direction::Up => y += 1,
+                Direction::Down => y -= 1,
+                Direction::Left => x -= 1,
+                Direction::Right => x += 1,
+            }
+            len += 1;
+        }
+        len
+    }
+
+    fn random_free_cell(&mut self, excluded: &[(i32, i32)]) -> Option<(i32, i32)> {
+        let mut taken = vec![false; ((self.borders.2 - self.borders.0) * (self.borders.3 - self.borders.1)) as usize];
+        for &cell in self.body.iter().chain(&self.walls).chain(excluded).chain([&self.apple]).chain(self.item.iter().map(|(_, pos, _)| pos)) {
+            taken[self.cell_index(cell)] = true;
+        }
+        for _ in 0..Snake::RANDOM_TRIES {
+            let random_x = self.rng.gen_range(self.borders.0 + 1..self.borders.2 - 1);
+            let random_y = self.rng.gen_range(self.borders.1 + 1..self.borders.3 - 1);
+            if !taken[self.cell_index((random_x, random_y))] {
+                return Some((random_x, random_y));
+            }
+        }
+        let cells = (self.borders.0 + 1..self.borders.2 - 1)
+            .flat_map(|x| (self.borders.1 + 1..self.borders.3 - 1).map(move |y| (x, y)))
+            .filter(|&cell| !taken[self.cell_index(cell)])
+            .collect::<Vec<_>>();
+        (!cells.is_empty()).then(|| cells[self.rng.gen_range(0..cells.len())])
+    }
+
+    fn random_apple_cell(&mut self, head: (i32, i32)) -> Option<(i32, i32)> {
+        let mut excluded = self.portals.iter().flat_map(|&(a, b)| [a, b]).collect::<Vec<_>>();
+        excluded.push(head);
+        self.random_free_cell(&excluded).or_else(|| self.random_free_cell(&[head]))
+    }
+
+    pub fn playable_cells(&self) -> usize {
+        ((self.borders.2 - self.borders.0 - 2) * (self.borders.3 - self.borders.1 - 2)) as usize - self.walls.len()
+    }
+
+    fn free_cells(&self) -> usize {
+        self.playable_cells().saturating_sub(self.body.len())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.free_cells() <= 1
+    }
+
+    pub fn portal_partner(&self, cell: (i32, i32)) -> Option<(i32, i32)> {
+        self.portals.iter().find_map(|&(a, b)| {
+            if a == cell {
+                Some(b)
+            } else if b == cell {
+                Some(a)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn set_portals(&mut self, portals: Vec<((i32, i32), (i32, i32))>) {
+        self.portals = portals;
+        if self.portal_partner(self.apple).is_some() {
+            if let Some(cell) = self.random_apple_cell(*self.body.front().unwrap()) {
+                self.apple = cell;
+            }
+        }
+    }
+
+    fn is_free(&self, cell: (i32, i32)) -> bool {
+        !self.blocked(cell) && !self.body.contains(&cell)
+    }
+
+    pub fn place_apple(&mut self, cell: (i32, i32)) {
+        if self.is_free(cell) && self.portal_partner(cell).is_none() && self.item.is_none_or(|(_, pos, _)| pos != cell) {
+            self.apple = cell;
+            self.apple_age = 0;
+        }
+    }
+
+    pub fn toggle_wall(&mut self, cell: (i32, i32)) {
+        if !self.walls.remove(&cell) && self.is_free(cell) && self.portal_partner(cell).is_none() && cell != self.apple && self.item.is_none_or(|(_, pos, _)| pos != cell) {
+            self.walls.insert(cell);
+        }
+    }
+
+    pub fn edit_body(&mut self, cell: (i32, i32)) {
+        if self.body.len() > 1 && self.body.back() == Some(&cell) {
+            self.body.pop_back();
+            return;
+        }
+        if !self.is_free(cell) || cell == self.apple || self.item.is_some_and(|(_, pos, _)| pos == cell) {
+            return;
+        }
+        let tail = *self.body.back().unwrap();
+        if (tail.0 - cell.0).abs() + (tail.1 - cell.1).abs() == 1 {
+            self.body.push_back(cell);
+        } else {
+            self.body = VecDeque::from([cell]);
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        Position {
+            borders: self.borders,
+            body: self.body.iter().copied().collect(),
+            direction: self.direction,
+            apple: self.apple,
+            walls: self.walls.iter().copied().collect(),
+            portals: self.portals.clone(),
+        }
+    }
+
+    pub fn set_position(&mut self, position: Position) {
+        self.body = position.body.into_iter().collect();
+        self.direction = position.direction;
+        self.apple = position.apple;
+        self.apple_age = 0;
+        self.walls = position.walls.into_iter().collect();
+        self.portals = position.portals;
+        self.item = None;
+        self.growth = 0;
+        self.last_tail = None;
+    }
+
+    pub fn state(&self) -> State {
+        let seed = self.rng.clone().gen::<i64>();
+        State {
+            borders: self.borders,
+            body: self.body.iter().copied().collect(),
+            direction: self.direction,
+            apple: self.apple,
+            apple_age: self.apple_age,
+            rules: self.rules,
+            walls: self.walls.iter().copied().collect(),
+            portals: self.portals.clone(),
+            item: self.item,
+            growth: self.growth,
+            last_tail: self.last_tail,
+            apples: self.apples,
+            steps: self.steps,
+            slow_motion: self.slow_motion,
+            ghost: self.ghost,
+            seed,
+            direction_path: self.direction_path.to_vec(),
+        }
+    }
+
+    pub fn from_state(state: &State) -> Result<Snake, String> {
+        let snake = Snake {
+            body: state.body.iter().copied().collect(),
+            direction: state.direction,
+            borders: state.borders,
+            apple: state.apple,
+            apple_age: state.apple_age,
+            direction_path: Arc::new(state.direction_path.clone()),
+            rules: state.rules,
+            walls: state.walls.iter().copied().collect(),
+            portals: state.portals.clone(),
+            item: state.item,
+            growth: state.growth,
+            last_tail: state.last_tail,
+            apples: state.apples,
+            steps: state.steps,
+            slow_motion: state.slow_motion,
+            ghost: state.ghost,
+            rng: Pcg64Mcg::seed_from_u64(state.seed as u64),
+        };
+        snake.position().check()?;
+        if snake.item.is_some_and(|(_, pos, _)| snake.blocked(pos)) {
+            return Err("the power-up is outside of the board".to_string());
+        }
+        if !snake.direction_path.is_empty() && snake.cycle_length() != Some(snake.direction_path.len()) {
+            return Err("the Hamiltonian cycle is broken".to_string());
+        }
+        Ok(snake)
+    }
+
+    fn cycle_length(&self) -> Option<usize> {
+        let cells = ((self.borders.2 - self.borders.0 - 2) * (self.borders.3 - self.borders.1 - 2)) as usize;
+        if self.direction_path.len() != cells {
+            return None;
+        }
+        let start = self.path_cell(0);
+        let (mut x, mut y) = start;
+        for len in 1..=cells {
+            match self.path_direction(x, y) {
+                Direction::Up => y += 1,
+                Direction::Down => y -= 1,
+                Direction::Left => x -= 1,
+                Direction::Right => x += 1,
+            }
+            if (x, y) == start {
+                return Some(len);
+            }
+            if x <= self.borders.0 || x >= self.borders.2 - 1 || y <= self.borders.1 || y >= self.borders.3 - 1 {
+                return None;
+            }
+        }
+        None
+    }
+
+    pub fn add_random_portals(&mut self, pairs: u32) {
+        let ahead = self.neighbour(*self.body.front().unwrap(), self.direction);
+        for _ in 0..pairs {
+            if self.free_cells() < 8 {
+                break;
+            }
+            let mut excluded = self.portals.iter().flat_map(|&(a, b)| [a, b]).collect::<Vec<_>>();
+            excluded.push(ahead);
+            let Some(a) = self.random_free_cell(&excluded) else {
+                break;
+            };
+            excluded.push(a);
+            let Some(b) = self.random_free_cell(&excluded) else {
+                break;
+            };
+            self.portals.push((a, b));
+        }
+    }
+
+    pub fn set_walls(&mut self, walls: HashSet<(i32, i32)>) {
+        self.walls = walls;
+        if self.walls.contains(&self.apple) {
+            if let Some(cell) = self.random_apple_cell(*self.body.front().unwrap()) {
+                self.apple = cell;
+            }
+        }
+    }
+
+    pub fn record(&self) -> Undo {
+        Undo {
+            head: self.body.front().copied(),
+            len: self.body.len(),
+            tail: self.body.iter().rev().take(Item::SHRINK_SEGMENTS + 1).copied().collect(),
+            direction: self.direction,
+            apple: self.apple,
+            apple_age: self.apple_age,
+            item: self.item,
+            growth: self.growth,
+            last_tail: self.last_tail,
+            apples: self.apples,
+            steps: self.steps,
+            slow_motion: self.slow_motion,
+            ghost: self.ghost,
+            rng: self.rng.clone(),
+        }
+    }
+
+    pub fn undo(&mut self, undo: &Undo) {
+        if self.body.front() != undo.head.as_ref() {
+            self.body.pop_front();
+        }
+        let missing = undo.len.saturating_sub(self.body.len()).min(undo.tail.len());
+        self.body.extend(undo.tail[..missing].iter().rev());
+        self.direction = undo.direction;
+        self.apple = undo.apple;
+        self.apple_age = undo.apple_age;
+        self.item = undo.item;
+        self.growth = undo.growth;
+        self.last_tail = undo.last_tail;
+        self.apples = undo.apples;
+        self.steps = undo.steps;
+        self.slow_motion = undo.slow_motion;
+        self.ghost = undo.ghost;
+        self.rng = undo.rng.clone();
+    }
+
+    pub fn step(&mut self) -> bool {
+        if self.body.is_empty() || self.free_cells() <= 1 {
+            return false;
+        }
+        self.steps += 1;
+        self.slow_motion = self.slow_motion.saturating_sub(1);
+        self.ghost = self.ghost.saturating_sub(1);
+
+        let (x, y) = self.neighbour(*self.body.front().unwrap(), self.direction);
+        if x == self.apple.0 && y == self.apple.1 {
+            self.growth += 1;
+            self.apples += 1;
+            match self.random_apple_cell((x, y)) {
+                Some(cell) => self.apple = cell,
+                None => {
+                    if let Some((_, pos, _)) = self.item.take() {
+                        self.apple = pos;
+                    }
+                }
+            }
+            self.apple_age = 0;
+            if self.rules.power_ups && self.item.is_none() && self.free_cells() > 3 {
+                if let Some(item) = Item::random(&mut self.rng) {
+                    if let Some(cell) = self.random_free_cell(&[(x, y)]) {
+                        self.item = Some((item, cell, self.apple_lifetime()));
+                    }
+                }
+            }
+        }
+
+        let mut shrink = 0;
+        match self.item {
+            Some((item, pos, _)) if pos == (x, y) => {
+                match item {
+                    Item::Golden => self.growth += Item::GOLDEN_GROWTH,
+                    Item::SlowMotion => self.slow_motion = Item::SLOW_MOTION_STEPS,
+                    Item::Shrink => shrink = Item::SHRINK_SEGMENTS,
+                    Item::Ghost => self.ghost = Item::GHOST_STEPS,
+                }
+                self.item = None;
+            }
+            Some((_, _, 0)) => self.item = None,
+            Some((item, pos, lifetime)) => self.item = Some((item, pos, lifetime - 1)),
+            None => {}
+        }
+
+        if self.growth > 0 {
+            self.growth -= 1;
+            self.last_tail = None;
+        } else {
+            self.last_tail = self.body.pop_back();
+        }
+        if self.blocked((x, y)) {
+            return false;
+        }
+        if self.body.contains(&(x, y)) {
+            if self.ghost == 0 {
+                return false;
+            }
+            self.ghost = 0;
+        }
+        self.body.push_front((x, y));
+        for _ in 0..shrink {
+            if self.body.len() <= 2 {
+                break;
+            }
+            self.body.pop_back();
+            self.last_tail = None;
+        }
+        self.age_apple();
+        true
+    }
+
+    pub fn apple_lifetime(&self) -> u32 {
+        2 * (self.borders.2 - self.borders.0 + self.borders.3 - self.borders.1) as u32
+    }
+
+    fn age_apple(&mut self) {
+        self.apple_age += 1;
+        match self.rules.apple {
+            AppleRule::Static => {}
+            AppleRule::Expiring => {
+                if self.apple_age >= self.apple_lifetime() {
+                    if let Some(cell) = self.random_apple_cell(*self.body.front().unwrap()) {
+                        self.apple = cell;
+                    }
+                    self.apple_age = 0;
+                }
+            }
+            AppleRule::Drifting => {
+                if !self.apple_age.is_multiple_of(AppleRule::DRIFT_INTERVAL) {
+                    return;
+                }
+                let offset = self.rng.gen_range(0..4);
+                let target = (0..4)
+                    .map(|i| self.neighbour(self.apple, Direction::ALL[(offset + i) % 4]))
+                    .find(|&cell| !self.blocked(cell) && !self.body.contains(&cell) && self.portal_partner(cell).is_none() && self.item.is_none_or(|(_, pos, _)| pos != cell));
+                if let Some(cell) = target {
+                    self.apple = cell;
+                }
+            }
+        }
+    }
+
+    fn random_dir(dir: Direction, rng: &mut impl Rng) -> Direction {
+        match dir {
+            Direction::Up => {
+                match rng.gen_range(0..3) {
+                    0 => Direction::Left,
+                    1 => Direction::Right,
+                    2 => Direction::Up,
+                    _ => unsafe { unreachable_unchecked() }
+                }
+            }
+            Direction::Down => {
+                match rng.gen_range(0..3) {
+                    0 => Direction::Left,
+                    1 => Direction::Right,
+                    2 => Direction::Up,
+                    _ => unsafe { unreachable_unchecked() }
+                }
+            }
+            Direction::Left => {
+                match rng.gen_range(0..3) {
+                    0 => Direction::Up,
+                    1 => Direction::Down,
+                    2 => Direction::Right,
+                    _ => unsafe { unreachable_unchecked() }
+                }
+            }
+            Direction::Right => {
+                match rng.gen_range(0..3) {
+                    0 => Direction::Up,
+                    1 => Direction::Down,
+                    2 => Direction::Left,
+                    _ => unsafe { unreachable_unchecked() }
+                }
+            }
+        }
+    }
+
+    fn neighbour(&self, (x, y): (i32, i32), direction: Direction) -> (i32, i32) {
+        let cell = match direction {
+            Direction::Up => (x, y + 1),
+            Direction::Down => (x, y - 1),
+            Direction::Left => (x - 1, y),
+            Direction::Right => (x + 1, y),
+        };
+        self.portal_partner(cell).unwrap_or(cell)
+    }
+
+    fn blocked(&self, (x, y): (i32, i32)) -> bool {
+        x <= self.borders.0 || x >= self.borders.2 - 1 || y <= self.borders.1 || y >= self.borders.3 - 1 || self.walls.contains(&(x, y))
+    }
+
+    fn cell_index(&self, (x, y): (i32, i32)) -> usize {
+        (x - self.borders.0) as usize + (y - self.borders.1) as usize * (self.borders.2 - self.borders.0) as usize
+    }
+
+    fn reachable_cells(&self, start: (i32, i32), occupied: &[bool]) -> usize {
+        let mut seen = occupied.to_vec();
+        let mut stack = vec![start];
+        seen[self.cell_index(start)] = true;
+        let mut count = 0;
+        while let Some(cell) = stack.pop() {
+            count += 1;
+            for direction in Direction::ALL {
+                let next = self.neighbour(cell, direction);
+                if !self.blocked(next) && !seen[self.cell_index(next)] {
+                    seen[self.cell_index(next)] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        count
+    }
+
+    fn path_to(&self, target: (i32, i32), occupied: &[bool]) -> Option<Vec<(i32, i32)>> {
+        let head = *self.body.front().unwrap();
+        let mut parent = vec![None; occupied.len()];
+        let mut seen = occupied.to_vec();
+        seen[self.cell_index(head)] = true;
+        let mut queue = VecDeque::from([head]);
+        while let Some(cell) = queue.pop_front() {
+            if cell == target {
+                let mut path = vec![cell];
+                while let Some(previous) = parent[self.cell_index(*path.last().unwrap())] {
+                    path.push(previous);
+                }
+                path.pop();
+                path.reverse();
+                return Some(path);
+            }
+            for direction in Direction::ALL {
+                let next = self.neighbour(cell, direction);
+                if !self.blocked(next) && !seen[self.cell_index(next)] {
+                    seen[self.cell_index(next)] = true;
+                    parent[self.cell_index(next)] = Some(cell);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    fn moved_body(&self, path: &[(i32, i32)]) -> VecDeque<(i32, i32)> {
+        let mut body = self.body.clone();
+        let mut growth = self.growth;
+        for &cell in path {
+            if cell == self.apple {
+                growth += 1;
+            }
+            if growth > 0 {
+                growth -= 1;
+            } else {
+                body.pop_back();
+            }
+            body.push_front(cell);
+        }
+        body
+    }
+
+    fn tail_reachable(&self, body: &VecDeque<(i32, i32)>) -> bool {
+        if body.len() < 3 || body.len() + 1 >= self.playable_cells() {
+            return true;
+        }
+        let tail = *body.back().unwrap();
+        let mut seen = vec![false; ((self.borders.2 - self.borders.0) * (self.borders.3 - self.borders.1)) as usize];
+        for &cell in body.iter().take(body.len() - 1) {
+            seen[self.cell_index(cell)] = true;
+        }
+        let mut stack = vec![*body.front().unwrap()];
+        while let Some(cell) = stack.pop() {
+            for direction in Direction::ALL {
+                let next = self.neighbour(cell, direction);
+                if next == tail {
+                    return true;
+                }
+                if !self.blocked(next) && !seen[self.cell_index(next)] {
+                    seen[self.cell_index(next)] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        false
+    }
+
+    fn search_move(&self) -> Direction {
+        let head = *self.body.front().unwrap();
+        let mut occupied = vec![false; ((self.borders.2 - self.borders.0) * (self.borders.3 - self.borders.1)) as usize];
+        let tail_moves = self.growth == 0 && self.body.len() > 1;
+        for &cell in self.body.iter().take(self.body.len() - tail_moves as usize) {
+            occupied[self.cell_index(cell)] = true;
+        }
+        let direction_to = |cell: (i32, i32)| Direction::ALL.into_iter().find(|&direction| self.neighbour(head, direction) == cell);
+
+        let starving = self.apple_age > Snake::STARVATION_FACTOR * self.playable_cells() as u32;
+        let hash = (self.steps as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+        if let Some(path) = self.path_to(self.apple, &occupied) {
+            let greedy = starving && hash.is_multiple_of(2) && self.tail_reachable(&self.moved_body(&path[..1]));
+            // Some endgames never leave a safe way to the apple, so a long stall ends with a risky grab
+            let desperate = self.apple_age > Snake::DESPERATION_FACTOR * self.playable_cells() as u32;
+            if desperate || greedy || self.tail_reachable(&self.moved_body(&path)) {
+                if let Some(direction) = direction_to(path[0]) {
+                    return direction;
+                }
+            }
+        }
+
+        let moves = Direction::ALL
+            .into_iter()
+            .map(|direction| (direction, self.neighbour(head, direction)))
+            .filter(|&(_, cell)| !self.blocked(cell) && !occupied[self.cell_index(cell)])
+            .collect::<Vec<_>>();
+        let safe = moves.iter().copied().filter(|&(_, cell)| self.tail_reachable(&self.moved_body(&[cell]))).collect::<Vec<_>>();
+        // Following the tail can repeat the same loop forever, so a starving bot wanders between safe moves
+        if starving && !safe.is_empty() {
+            return safe[(hash >> 1) as usize % safe.len()].0;
+        }
+        let candidates = if safe.is_empty() { moves } else { safe };
+        candidates
+            .into_iter()
+            .max_by_key(|&(direction, cell)| (self.reachable_cells(cell, &occupied), direction == self.direction))
+            .map_or(self.direction, |(direction, _)| direction)
+    }
+
+    pub fn bot_move(&self) -> Direction {
+        if !self.walls.is_empty() || !self.portals.is_empty() || self.direction_path.is_empty() {
+            return self.search_move();
+        }
+        let &(x, y) = self.body.front().unwrap();
+        let mut dir = self.path_direction(x, y);
+        if !self.rules.shortcuts {
+            return dir;
+        }
+        let mut path_len = self.path_len(x, y);
+        let snake_len = self.body.len() as u32;
+
+        if y < self.borders.3 - 2
+        && x < self.borders.2 - 2
+        && self.path_len(x, y + 1) + 1 < path_len
+        && !self.body.contains(&(x, y + 1))
+        && snake_len <= self.free_path_len(x, y + 1, snake_len) {
+            path_len = self.path_len(x, y + 1) + 1;
+            dir = Direction::Up;
+        }
+        if y > self.borders.1 + 1
+        && x < self.borders.2 - 2
+        && self.path_len(x, y - 1) + 1 < path_len
+        && !self.body.contains(&(x, y - 1))
+        && snake_len <= self.free_path_len(x, y - 1, snake_len) {
+            path_len = self.path_len(x, y - 1) + 1;
+            dir = Direction::Down;
+        }
+
+        if x > self.borders.0 + 1
+        && y < self.borders.3 - 2
+        && self.path_len(x - 1, y) + 1 < path_len
+        && !self.body.contains(&(x - 1, y))
+        && snake_len <= self.free_path_len(x - 1, y, snake_len) {
+            path_len = self.path_len(x - 1, y) + 1;
+            dir = Direction::Left;
+        }
+
+        if x < self.borders.2 - 2
+        && y < self.borders.3 - 2
+        && self.path_len(x + 1, y) + 1 < path_len
+        && !self.body.contains(&(x + 1, y))
+        && snake_len <= self.free_path_len(x + 1, y, snake_len) {
+            dir = Direction::Right;
+        }
+        dir
+    }
+}
@@ -0,0 +1,69 @@
+use std::{fs, path::PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+fn file_in(dir: Option<PathBuf>, name: &str) -> Option<PathBuf> {
+    let dir = dir?.join("snake");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(name))
+}
+
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    file_in(dirs::data_dir(), name)
+}
+
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    file_in(dirs::config_dir(), name)
+}
+
+fn load_from<T: DeserializeOwned>(path: Option<PathBuf>, name: &str) -> Option<T> {
+    path.and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str(&content).map_err(|e| eprintln!("Could not parse {}: {}", name, e)).ok())
+}
+
+pub fn try_load<T: DeserializeOwned>(name: &str) -> Option<T> {
+    load_from(data_file(name), name)
+}
+
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    try_load(name).unwrap_or_default()
+}
+
+pub fn try_load_config<T: DeserializeOwned>(name: &str) -> Option<T> {
+    load_from(config_file(name), name)
+}
+
+pub fn load_config<T: DeserializeOwned + Default>(name: &str) -> T {
+    try_load_config(name).unwrap_or_default()
+}
+
+pub fn write(name: &str, content: &str) -> Option<PathBuf> {
+    let Some(path) = data_file(name) else {
+        eprintln!("No data directory available, {} was not saved", name);
+        return None;
+    };
+    match fs::write(&path, content) {
+        Ok(()) => Some(path),
+        Err(e) => {
+            eprintln!("Could not save {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) {
+    match toml::to_string(value) {
+        Ok(content) => {
+            write(name, &content);
+        }
+        Err(e) => eprintln!("Could not save {}: {}", name, e),
+    }
+}
+
+pub fn remove(name: &str) {
+    if let Some(path) = data_file(name).filter(|path| path.exists()) {
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Could not remove {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs;
+
+use nannou::color::{rgba, Rgba};
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Color {
+        Color { r, g, b, a: 1.0 }
+    }
+
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub fn with_alpha(self, a: f32) -> Color {
+        Color { a: self.a * a, ..self }
+    }
+
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        Color {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
+        }
+    }
+}
+
+impl From<Color> for Rgba {
+    fn from(color: Color) -> Self {
+        rgba(color.r, color.g, color.b, color.a)
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Self, Self::Error> {
+        let digits = hex.trim_start_matches('#');
+        if digits.len() != 6 && digits.len() != 8 {
+            return Err(format!("expected #rrggbb or #rrggbbaa, got {}", hex));
+        }
+        let channel = |i: usize| {
+            digits.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .map(|value| value as f32 / 255.0)
+                .ok_or_else(|| format!("invalid color {}", hex))
+        };
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: if digits.len() == 8 { channel(6)? } else { 1.0 },
+        })
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        if color.a < 1.0 {
+            format!("#{:02x}{:02x}{:02x}{:02x}", channel(color.r), channel(color.g), channel(color.b), channel(color.a))
+        } else {
+            format!("#{:02x}{:02x}{:02x}", channel(color.r), channel(color.g), channel(color.b))
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    pub border: Color,
+    pub board_light: Color,
+    pub board_dark: Color,
+    pub snake_head: Color,
+    pub snake_tail: Color,
+    pub apple: Color,
+    pub path: Color,
+    pub golden: Color,
+    pub slow_motion: Color,
+    pub shrink: Color,
+    pub ghost: Color,
+    pub text: Color,
+    pub highlight: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: "Classic".to_string(),
+            background: Color::rgb(0.0, 0.0, 0.0),
+            border: Color::rgb(0.2, 0.4, 0.2),
+            board_light: Color::rgb(0.02, 0.2, 0.02),
+            board_dark: Color::rgb(0.04, 0.1, 0.04),
+            snake_head: Color::rgb(20.0 / 255.0, 1.0, 20.0 / 255.0),
+            snake_tail: Color::rgb(20.0 / 255.0, 150.0 / 255.0, 20.0 / 255.0),
+            apple: Color::rgb(1.0, 0.0, 0.0),
+            path: Color::rgba(1.0, 1.0, 1.0, 0.3),
+            golden: Color::rgb(1.0, 0.84, 0.0),
+            slow_motion: Color::rgb(0.2, 0.5, 1.0),
+            shrink: Color::rgb(0.7, 0.2, 0.9),
+            ghost: Color::rgba(1.0, 1.0, 1.0, 0.5),
+            text: Color::rgb(1.0, 1.0, 1.0),
+            highlight: Color::rgb(1.0, 0.84, 0.0),
+        }
+    }
+}
+
+impl Theme {
+    pub fn builtin() -> Vec<Theme> {
+        vec![
+            Theme::default(),
+            Theme {
+                name: "High contrast".to_string(),
+                background: Color::rgb(0.0, 0.0, 0.0),
+                border: Color::rgb(1.0, 1.0, 1.0),
+                board_light: Color::rgb(0.12, 0.12, 0.12),
+                board_dark: Color::rgb(0.0, 0.0, 0.0),
+                snake_head: Color::rgb(1.0, 1.0, 0.0),
+                snake_tail: Color::rgb(0.75, 0.75, 0.0),
+                apple: Color::rgb(1.0, 0.0, 1.0),
+                path: Color::rgba(0.0, 1.0, 1.0, 0.5),
+                golden: Color::rgb(1.0, 0.6, 0.0),
+                slow_motion: Color::rgb(0.0, 0.8, 1.0),
+                shrink: Color::rgb(1.0, 0.2, 0.2),
+                ghost: Color::rgba(1.0, 1.0, 1.0, 0.7),
+                text: Color::rgb(1.0, 1.0, 1.0),
+                highlight: Color::rgb(1.0, 1.0, 0.0),
+            },
+            Theme {
+                name: "Color-blind friendly".to_string(),
+                background: Color::rgb(0.05, 0.05, 0.08),
+                border: Color::rgb(0.45, 0.45, 0.5),
+                board_light: Color::rgb(0.16, 0.17, 0.22),
+                board_dark: Color::rgb(0.11, 0.12, 0.16),
+                snake_head: Color::rgb(0.34, 0.71, 0.91),
+                snake_tail: Color::rgb(0.0, 0.45, 0.7),
+                apple: Color::rgb(0.9, 0.62, 0.0),
+                path: Color::rgba(1.0, 1.0, 1.0, 0.25),
+                golden: Color::rgb(0.94, 0.89, 0.26),
+                slow_motion: Color::rgb(0.0, 0.62, 0.45),
+                shrink: Color::rgb(0.8, 0.47, 0.65),
+                ghost: Color::rgba(1.0, 1.0, 1.0, 0.5),
+                text: Color::rgb(1.0, 1.0, 1.0),
+                highlight: Color::rgb(0.9, 0.62, 0.0),
+            },
+            Theme {
+                name: "Paper".to_string(),
+                background: Color::rgb(0.96, 0.94, 0.88),
+                border: Color::rgb(0.55, 0.5, 0.42),
+                board_light: Color::rgb(0.92, 0.89, 0.8),
+                board_dark: Color::rgb(0.87, 0.84, 0.74),
+                snake_head: Color::rgb(0.25, 0.25, 0.3),
+                snake_tail: Color::rgb(0.5, 0.5, 0.55),
+                apple: Color::rgb(0.8, 0.15, 0.1),
+                path: Color::rgba(0.0, 0.0, 0.0, 0.2),
+                golden: Color::rgb(0.85, 0.6, 0.0),
+                slow_motion: Color::rgb(0.1, 0.4, 0.8),
+                shrink: Color::rgb(0.5, 0.1, 0.6),
+                ghost: Color::rgba(0.3, 0.3, 0.3, 0.4),
+                text: Color::rgb(0.1, 0.1, 0.1),
+                highlight: Color::rgb(0.8, 0.15, 0.1),
+            },
+        ]
+    }
+
+    pub fn load_all() -> Vec<Theme> {
+        let mut themes = Theme::builtin();
+        let Some(dir) = crate::storage::config_file("themes") else {
+            return themes;
+        };
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return themes;
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            let theme = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| toml::from_str::<Theme>(&content).map_err(|e| e.to_string()));
+            match theme {
+                Ok(theme) => themes.push(theme),
+                Err(e) => eprintln!("Could not load theme {}: {}", path.display(), e),
+            }
+        }
+        themes
+    }
+
+    pub fn with_colors(&self, colors: &toml::Table) -> Result<Theme, String> {
+        let mut value = toml::Value::try_from(self).map_err(|e| e.to_string())?;
+        let table = value.as_table_mut().unwrap();
+        for (name, color) in colors {
+            if name == "name" || !table.contains_key(name) {
+                return Err(format!("Unknown color {}", name));
+            }
+            table.insert(name.clone(), color.clone());
+        }
+        value.try_into().map_err(|e: toml::de::Error| e.to_string())
+    }
+
+    pub fn snake_color(&self, fraction: f32) -> Color {
+        self.snake_tail.lerp(self.snake_head, fraction)
+    }
+}
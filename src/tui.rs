@@ -0,0 +1,236 @@
+use std::{io::{self, BufWriter, Write}, time::{Duration, Instant}};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    style::{self, Print, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use nannou::event::Key;
+
+use crate::{input::{parse_key, Action, KeyBindings}, settings::Settings, snake::Item, theme::Color, GameArgs, Model, Screen};
+
+const FRAME_TIME: Duration = Duration::from_millis(33);
+
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), SetBackgroundColor(style::Color::Reset), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub fn run(settings: Settings, game: GameArgs) -> io::Result<()> {
+    let resume = !game.new_game;
+    let mut model = Model::create(settings, game, resume);
+    let _terminal = Terminal::enter()?;
+    let mut last = Instant::now();
+    let mut fps = 0.0;
+    loop {
+        let deadline = last + FRAME_TIME;
+        while event::poll(deadline.saturating_duration_since(Instant::now()))? {
+            let Event::Key(press) = event::read()? else {
+                continue;
+            };
+            if press.kind == KeyEventKind::Release {
+                continue;
+            }
+            if press.code == KeyCode::Char('c') && press.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(());
+            }
+            if let Some(key) = terminal_key(press.code) {
+                key_pressed(&mut model, key);
+            }
+        }
+        let dt = last.elapsed().as_secs_f32();
+        last = Instant::now();
+        fps = fps * 0.9 + 0.1 / dt.max(0.001);
+        model.reload_settings(dt);
+        model.tick(dt);
+        draw(&model, fps)?;
+    }
+}
+
+fn key_pressed(model: &mut Model, key: Key) {
+    let actions = model.bindings.actions(key).collect::<Vec<_>>();
+    let screen = model.screen;
+    for action in actions {
+        if model.screen != screen {
+            break;
+        }
+        if action != Action::Fullscreen {
+            model.trigger(action);
+        }
+    }
+}
+
+fn terminal_key(code: KeyCode) -> Option<Key> {
+    match code {
+        KeyCode::Char(c) if c.is_ascii_alphabetic() => parse_key(&c.to_string()),
+        KeyCode::Char(c) if c.is_ascii_digit() => parse_key(&format!("Key{}", c)),
+        KeyCode::Char(' ') => Some(Key::Space),
+        KeyCode::Char(',') => Some(Key::Comma),
+        KeyCode::Char('.') => Some(Key::Period),
+        KeyCode::Char('-') => Some(Key::Minus),
+        KeyCode::Char('=') => Some(Key::Equals),
+        KeyCode::Char('+') => Some(Key::Plus),
+        KeyCode::Char('/') => Some(Key::Slash),
+        KeyCode::Char(';') => Some(Key::Semicolon),
+        KeyCode::Char('\'') => Some(Key::Apostrophe),
+        KeyCode::Char('[') => Some(Key::LBracket),
+        KeyCode::Char(']') => Some(Key::RBracket),
+        KeyCode::Char('\\') => Some(Key::Backslash),
+        KeyCode::Char('`') => Some(Key::Grave),
+        KeyCode::F(n) => parse_key(&format!("F{}", n)),
+        KeyCode::Up => Some(Key::Up),
+        KeyCode::Down => Some(Key::Down),
+        KeyCode::Left => Some(Key::Left),
+        KeyCode::Right => Some(Key::Right),
+        KeyCode::Enter => Some(Key::Return),
+        KeyCode::Tab => Some(Key::Tab),
+        KeyCode::Backspace => Some(Key::Back),
+        KeyCode::Esc => Some(Key::Escape),
+        KeyCode::Insert => Some(Key::Insert),
+        KeyCode::Delete => Some(Key::Delete),
+        KeyCode::Home => Some(Key::Home),
+        KeyCode::End => Some(Key::End),
+        KeyCode::PageUp => Some(Key::PageUp),
+        KeyCode::PageDown => Some(Key::PageDown),
+        _ => None,
+    }
+}
+
+fn terminal_color(color: Color, background: Color) -> style::Color {
+    let color = background.lerp(Color { a: 1.0, ..color }, color.a);
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    style::Color::Rgb { r: channel(color.r), g: channel(color.g), b: channel(color.b) }
+}
+
+struct Canvas<W: Write> {
+    out: W,
+    row: u16,
+    colors: Option<(style::Color, style::Color)>,
+}
+
+impl<W: Write> Canvas<W> {
+    fn cell(&mut self, text: &str, foreground: style::Color, background: style::Color) -> io::Result<()> {
+        if self.colors != Some((foreground, background)) {
+            queue!(self.out, SetForegroundColor(foreground), SetBackgroundColor(background))?;
+            self.colors = Some((foreground, background));
+        }
+        queue!(self.out, Print(text))
+    }
+
+    fn line(&mut self, text: &str, foreground: style::Color, background: style::Color) -> io::Result<()> {
+        self.cell(text, foreground, background)?;
+        self.end_line(background)
+    }
+
+    fn end_line(&mut self, background: style::Color) -> io::Result<()> {
+        self.colors = None;
+        self.row += 1;
+        queue!(self.out, SetBackgroundColor(background), Clear(ClearType::UntilNewLine), MoveTo(0, self.row))
+    }
+}
+
+fn draw(model: &Model, fps: f32) -> io::Result<()> {
+    let theme = model.theme();
+    let background = terminal_color(theme.background, theme.background);
+    let text = terminal_color(theme.text, theme.background);
+    let highlight = terminal_color(theme.highlight, theme.background);
+    let mut canvas = Canvas { out: BufWriter::new(io::stdout().lock()), row: 0, colors: None };
+    queue!(canvas.out, MoveTo(0, 0))?;
+
+    if model.show_help {
+        canvas.line("Bindings", highlight, background)?;
+        for (action, keys) in model.bindings.describe() {
+            canvas.line(&format!("{:<46}{}", action.description(), keys), text, background)?;
+        }
+        canvas.line(&format!("Rebind keys in {} or {}", KeyBindings::location(), Settings::location()), text, background)?;
+    } else if model.screen == Screen::LevelSelect {
+        canvas.line("Campaign", highlight, background)?;
+        canvas.end_line(background)?;
+        for level in 0..crate::LEVELS.len() {
+            let color = if level == model.selected_level { highlight } else { text };
+            canvas.line(&model.level_entry(level), color, background)?;
+        }
+    } else {
+        draw_board(model, &mut canvas, background)?;
+        canvas.line(&model.status_line().unwrap_or_default(), text, background)?;
+        if model.screen == Screen::GameOver {
+            for (line, highlighted) in model.end_lines() {
+                canvas.line(&line, if highlighted { highlight } else { text }, background)?;
+            }
+        }
+        if model.show_hud {
+            let hud = model.hud_lines(fps);
+            for pair in hud.chunks(2) {
+                canvas.line(&format!("{:<24}{}", pair[0], pair.get(1).map_or("", |line| line.as_str())), text, background)?;
+            }
+        }
+    }
+    canvas.line("Ctrl+C quits", text, background)?;
+
+    queue!(canvas.out, Clear(ClearType::FromCursorDown))?;
+    canvas.out.flush()
+}
+
+fn draw_board<W: Write>(model: &Model, canvas: &mut Canvas<W>, background: style::Color) -> io::Result<()> {
+    let theme = model.theme();
+    let snake = &model.snake;
+    let (x0, y0, x1, y1) = snake.borders;
+    let len = snake.body.len();
+    let alpha = if snake.ghost > 0 { 0.5 } else { 1.0 };
+    let width = (x1 - x0) as usize;
+    let mut segments = vec![None; width * (y1 - y0) as usize];
+    for (i, &(x, y)) in snake.body.iter().enumerate().rev() {
+        segments[(y - y0) as usize * width + (x - x0) as usize] = Some(i);
+    }
+    for y in (y0..y1).rev() {
+        for x in x0..x1 {
+            let cell = (x, y);
+            let board = if x == x0 || x == x1 - 1 || y == y0 || y == y1 - 1 || snake.walls.contains(&cell) {
+                theme.border
+            } else if (x + y).rem_euclid(2) == 0 {
+                theme.board_light
+            } else {
+                theme.board_dark
+            };
+            let board_color = terminal_color(board, theme.background);
+            let item = snake.item.filter(|&(_, position, _)| position == cell).map(|(item, _, _)| item);
+            if let Some(i) = segments[(y - y0) as usize * width + (x - x0) as usize] {
+                let color = theme.snake_color((len - i) as f32 / len as f32).with_alpha(alpha);
+                let glyph = if i == 0 { "▓▓" } else { "██" };
+                canvas.cell(glyph, terminal_color(color, board), board_color)?;
+            } else if cell == snake.apple {
+                canvas.cell("▐▌", terminal_color(theme.apple, board), board_color)?;
+            } else if let Some(item) = item {
+                let (glyph, color) = match item {
+                    Item::Golden => ("▐▌", theme.golden),
+                    Item::SlowMotion => ("◖◗", theme.slow_motion),
+                    Item::Shrink => ("◆ ", theme.shrink),
+                    Item::Ghost => ("░░", theme.ghost),
+                };
+                canvas.cell(glyph, terminal_color(color, board), board_color)?;
+            } else if let Some(pair) = snake.portals.iter().position(|&(a, b)| a == cell || b == cell) {
+                let hue = nannou::color::Hsv::new(360.0 * (pair as f32 / snake.portals.len() as f32 + 0.5), 0.8, 1.0);
+                let rgb = nannou::color::Rgb::from(hue);
+                canvas.cell("()", terminal_color(Color::rgb(rgb.red, rgb.green, rgb.blue), board), board_color)?;
+            } else {
+                canvas.cell("  ", board_color, board_color)?;
+            }
+        }
+        canvas.end_line(background)?;
+    }
+    Ok(())
+}